@@ -2,11 +2,7 @@
 
 case "${TARGET}" in
   web)
-    docker build --rm -t ${DOCKER_IMAGE} \
-      --build-arg RUST_VERSION=${TRAVIS_RUST_VERSION} \
-      --build-arg BUILD_UID=$(id -u) \
-      --build-arg BUILD_GID=$(id -g) \
-      ci
+    rustup target add wasm32-unknown-unknown
     ;;
   native)
     ;;
@@ -14,4 +10,3 @@ case "${TARGET}" in
     exit 1
     ;;
 esac
-
@@ -2,7 +2,7 @@
 
 case "${TARGET}" in
   web)
-    docker run -v $(pwd):/build -w /build ${DOCKER_IMAGE} ci/script_docker.sh
+    make
     ;;
   native)
     cargo build --verbose
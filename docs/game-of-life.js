@@ -0,0 +1,372 @@
+// Runs the WebAssembly build of game-of-life-rs in a canvas.
+//
+// The module does no I/O of its own: this script forwards input events to it and drives it with
+// requestAnimationFrame, copying each new frame out of its memory into the canvas and drawing
+// the heads-up display on top.
+//
+// `startGameOfLife` resolves to a controller for driving the simulation from the page:
+//
+//     startGameOfLife(canvas, 'game-of-life-rs.wasm').then(function (game) {
+//       game.loadRle('x = 3, y = 1\n3o!');
+//       game.onGeneration(function (generation, population) { ... });
+//       game.start();
+//     });
+
+'use strict';
+
+var HUD_FONT = '14px monospace';
+var HUD_LINE_HEIGHT = 18;
+var HUD_MARGIN = 8;
+
+// The toolbar along the bottom of the canvas, for screens without a keyboard.
+var TOOLBAR_FONT = '16px sans-serif';
+var TOOLBAR_BUTTON_WIDTH = 80;
+var TOOLBAR_BUTTON_HEIGHT = 44;
+var TOOLBAR_MARGIN = 8;
+
+// Writes the board and the view into the URL, for sharing.
+var LINK_KEY = 'KeyL';
+
+// The board, settings and view are kept in localStorage under this key, saved every
+// SAVE_INTERVAL milliseconds and when the page is left, and restored on the next visit.
+var STORAGE_KEY = 'game-of-life-rs.session';
+var SAVE_INTERVAL = 10000;
+
+function startGameOfLife(canvas, wasmUrl) {
+  return fetch(wasmUrl)
+    .then(function (response) {
+      if (!response.ok) {
+        throw new Error(wasmUrl + ': ' + response.status + ' ' + response.statusText);
+      }
+      return response.arrayBuffer();
+    })
+    .then(function (bytes) { return WebAssembly.instantiate(bytes, {}); })
+    .then(function (result) { return runGameOfLife(canvas, result.instance.exports); });
+}
+
+function runGameOfLife(canvas, wasm) {
+  var ctx = canvas.getContext('2d');
+  var image = null;
+  var decoder = new TextDecoder();
+  var encoder = new TextEncoder();
+  var listeners = [];
+  var lastGeneration = null;
+  // The touch that acts as the mouse, while there is only one.
+  var mouseTouch = null;
+  var showToolbar = window.matchMedia('(any-pointer: coarse)').matches;
+
+  // Sizes the canvas to the space the page gives it.
+  function fit() {
+    canvas.width = canvas.clientWidth;
+    canvas.height = canvas.clientHeight;
+    image = ctx.createImageData(canvas.width, canvas.height);
+  }
+
+  function passString(s, f) {
+    var bytes = encoder.encode(s);
+    var ptr = wasm.gol_alloc(bytes.length);
+    new Uint8Array(wasm.memory.buffer, ptr, bytes.length).set(bytes);
+    try {
+      return f(ptr, bytes.length);
+    } finally {
+      wasm.gol_free(ptr, bytes.length);
+    }
+  }
+
+  function readString(ptr, len) {
+    return decoder.decode(new Uint8Array(wasm.memory.buffer, ptr, len));
+  }
+
+  // Calls `f` with a string argument, throwing the module's error message if it fails.
+  function call(f, s) {
+    if (!passString(s, f)) {
+      throw new Error(readString(wasm.gol_error(), wasm.gol_error_len()));
+    }
+  }
+
+  function drawHud() {
+    var len = wasm.gol_hud_len();
+    if (len === 0) {
+      return;
+    }
+    var lines = readString(wasm.gol_hud(), len).split('\n');
+    ctx.font = HUD_FONT;
+    var width = Math.max.apply(null, lines.map(function (line) {
+      return ctx.measureText(line).width;
+    }));
+    ctx.fillStyle = 'rgba(0, 0, 0, 0.6)';
+    ctx.fillRect(0, 0, width + HUD_MARGIN * 2, HUD_LINE_HEIGHT * lines.length + HUD_MARGIN * 2);
+    ctx.fillStyle = 'white';
+    lines.forEach(function (line, i) {
+      ctx.fillText(line, HUD_MARGIN, HUD_MARGIN + HUD_LINE_HEIGHT * (i + 1) - 4);
+    });
+  }
+
+  function toolbarButtons() {
+    return [
+      { label: controller.isRunning() ? 'Pause' : 'Run', action: controller.toggleRunning },
+      { label: 'Step', action: controller.step },
+      { label: 'Clear', action: controller.clear },
+      { label: 'Random', action: controller.random },
+    ].map(function (button, i, buttons) {
+      var width = buttons.length * (TOOLBAR_BUTTON_WIDTH + TOOLBAR_MARGIN) - TOOLBAR_MARGIN;
+      button.x = (canvas.width - width) / 2 + i * (TOOLBAR_BUTTON_WIDTH + TOOLBAR_MARGIN);
+      button.y = canvas.height - TOOLBAR_BUTTON_HEIGHT - TOOLBAR_MARGIN;
+      return button;
+    });
+  }
+
+  function toolbarButtonAt(x, y) {
+    if (!showToolbar) {
+      return null;
+    }
+    return toolbarButtons().find(function (button) {
+      return x >= button.x && x < button.x + TOOLBAR_BUTTON_WIDTH &&
+        y >= button.y && y < button.y + TOOLBAR_BUTTON_HEIGHT;
+    }) || null;
+  }
+
+  function drawToolbar() {
+    if (!showToolbar) {
+      return;
+    }
+    ctx.save();
+    ctx.font = TOOLBAR_FONT;
+    ctx.textAlign = 'center';
+    ctx.textBaseline = 'middle';
+    toolbarButtons().forEach(function (button) {
+      ctx.fillStyle = 'rgba(0, 0, 0, 0.6)';
+      ctx.fillRect(button.x, button.y, TOOLBAR_BUTTON_WIDTH, TOOLBAR_BUTTON_HEIGHT);
+      ctx.fillStyle = 'white';
+      ctx.fillText(button.label, button.x + TOOLBAR_BUTTON_WIDTH / 2,
+                   button.y + TOOLBAR_BUTTON_HEIGHT / 2);
+    });
+    ctx.restore();
+  }
+
+  // Tells the listeners about a new generation, or a new board.
+  function notify(force) {
+    var generation = wasm.gol_generation();
+    if (generation === lastGeneration && !force) {
+      return;
+    }
+    lastGeneration = generation;
+    var population = wasm.gol_population();
+    listeners.slice().forEach(function (listener) { listener(generation, population); });
+  }
+
+  function frame(time) {
+    var pixels = wasm.gol_frame(time);
+    if (pixels !== 0) {
+      image.data.set(new Uint8ClampedArray(wasm.memory.buffer, pixels, image.data.length));
+    }
+    ctx.putImageData(image, 0, 0);
+    drawHud();
+    drawToolbar();
+    notify();
+    window.requestAnimationFrame(frame);
+  }
+
+  // Restores the last session from localStorage, returning whether there was one.
+  function restoreSession() {
+    try {
+      var text = window.localStorage.getItem(STORAGE_KEY);
+      if (text === null) {
+        return false;
+      }
+      call(wasm.gol_restore, text);
+      return true;
+    } catch (e) {
+      console.error('failed to restore session: ' + e.message);
+      return false;
+    }
+  }
+
+  function saveSession() {
+    try {
+      var text = readString(wasm.gol_session(), wasm.gol_session_len());
+      window.localStorage.setItem(STORAGE_KEY, text);
+    } catch (e) {
+      console.error('failed to save session: ' + e.message);
+    }
+  }
+
+  // Opens the pattern and view in the fragment of the page URL, if there is one.
+  function openLink() {
+    var fragment = window.location.hash.slice(1);
+    if (fragment === '') {
+      return;
+    }
+    try {
+      call(wasm.gol_open_link, fragment);
+      notify(true);
+    } catch (e) {
+      console.error('failed to open link: ' + e.message);
+    }
+  }
+
+  function writeLink() {
+    var fragment = readString(wasm.gol_link(), wasm.gol_link_len());
+    window.history.replaceState(null, '', '#' + fragment);
+  }
+
+  // Passes a touch event on, with the `phase` of `gol_touch`. As long as there is only one touch
+  // it also acts as the mouse with the left button held, so that it draws.
+  function touch(e, phase) {
+    e.preventDefault();
+    var rect = canvas.getBoundingClientRect();
+    Array.prototype.forEach.call(e.changedTouches, function (t) {
+      var x = t.clientX - rect.left;
+      var y = t.clientY - rect.top;
+      if (phase === 0 && e.touches.length === 1) {
+        var button = toolbarButtonAt(x, y);
+        if (button !== null) {
+          button.action();
+          return;
+        }
+        mouseTouch = t.identifier;
+        wasm.gol_mouse_move(x, y);
+        wasm.gol_mouse_button(0, true);
+      } else if (t.identifier === mouseTouch) {
+        wasm.gol_mouse_move(x, y);
+        if (phase >= 2) {
+          wasm.gol_mouse_button(0, false);
+          mouseTouch = null;
+        }
+      }
+      wasm.gol_touch(t.identifier, x, y, phase);
+    });
+    // A second finger turns the stroke into a pan or zoom.
+    if (e.touches.length > 1 && mouseTouch !== null) {
+      wasm.gol_mouse_button(0, false);
+      mouseTouch = null;
+    }
+  }
+
+  function key(e, pressed) {
+    if (pressed && (e.ctrlKey || e.altKey || e.metaKey || e.repeat)) {
+      return;
+    }
+    if (e.code === LINK_KEY) {
+      if (pressed) {
+        writeLink();
+      }
+      e.preventDefault();
+      return;
+    }
+    var handled = passString(e.code, function (ptr, len) {
+      return wasm.gol_key(ptr, len, pressed);
+    });
+    if (handled) {
+      e.preventDefault();
+    }
+  }
+
+  fit();
+  var seed = new Uint32Array(4);
+  window.crypto.getRandomValues(seed);
+  wasm.gol_start(canvas.width, canvas.height, seed[0], seed[1], seed[2], seed[3] | 1);
+  if (!restoreSession()) {
+    wasm.gol_reset();
+  }
+  openLink();
+
+  // Keys go to the simulator only while the canvas has the focus, so that the page around it
+  // keeps working.
+  if (canvas.tabIndex < 0) {
+    canvas.tabIndex = 0;
+  }
+
+  window.addEventListener('hashchange', openLink);
+  window.addEventListener('pagehide', saveSession);
+  window.setInterval(saveSession, SAVE_INTERVAL);
+  window.addEventListener('resize', function () {
+    fit();
+    wasm.gol_resize(canvas.width, canvas.height);
+  });
+  canvas.addEventListener('mousemove', function (e) {
+    wasm.gol_mouse_move(e.offsetX, e.offsetY);
+  });
+  canvas.addEventListener('mousedown', function (e) {
+    canvas.focus();
+    e.preventDefault();
+    var button = e.button === 0 ? toolbarButtonAt(e.offsetX, e.offsetY) : null;
+    if (button !== null) {
+      button.action();
+      return;
+    }
+    wasm.gol_mouse_button(e.button, true);
+  });
+  window.addEventListener('mouseup', function (e) {
+    wasm.gol_mouse_button(e.button, false);
+  });
+  canvas.addEventListener('contextmenu', function (e) { e.preventDefault(); });
+  canvas.addEventListener('wheel', function (e) {
+    wasm.gol_wheel(-Math.sign(e.deltaY) / 2);
+    e.preventDefault();
+  });
+  canvas.addEventListener('keydown', function (e) { key(e, true); });
+  canvas.addEventListener('keyup', function (e) { key(e, false); });
+
+  // Touches move the view instead of scrolling the page.
+  canvas.style.touchAction = 'none';
+  var touchOptions = { passive: false };
+  canvas.addEventListener('touchstart', function (e) {
+    showToolbar = true;
+    touch(e, 0);
+  }, touchOptions);
+  canvas.addEventListener('touchmove', function (e) { touch(e, 1); }, touchOptions);
+  canvas.addEventListener('touchend', function (e) { touch(e, 2); }, touchOptions);
+  canvas.addEventListener('touchcancel', function (e) { touch(e, 3); }, touchOptions);
+
+  var controller = {
+    // Replaces the board with a pattern in RLE (or plaintext or Life 1.06) format. Throws an
+    // Error if it cannot be read.
+    loadRle: function (text) {
+      call(wasm.gol_load_rle, text);
+      notify(true);
+    },
+    // Sets the rule, such as 'B36/S23'. Throws an Error if it is not a valid rule.
+    setRule: function (rule) { call(wasm.gol_set_rule, rule); },
+    isRunning: function () { return wasm.gol_is_running() !== 0; },
+    toggleRunning: function () { wasm.gol_toggle_running(); },
+    start: function () {
+      if (!wasm.gol_is_running()) {
+        wasm.gol_toggle_running();
+      }
+    },
+    pause: function () {
+      if (wasm.gol_is_running()) {
+        wasm.gol_toggle_running();
+      }
+    },
+    // Advances the board by one generation. Does nothing while it is running.
+    step: function () {
+      wasm.gol_step();
+      notify();
+    },
+    clear: function () {
+      wasm.gol_clear();
+      notify(true);
+    },
+    // Replaces the board with a random one of the same size.
+    random: function () {
+      wasm.gol_random();
+      notify(true);
+    },
+    generation: function () { return wasm.gol_generation(); },
+    population: function () { return wasm.gol_population(); },
+    // Calls `listener(generation, population)` after each generation, and when a new board
+    // resets the count. While running, the board advances once per animation frame.
+    // Returns a function that removes the listener.
+    onGeneration: function (listener) {
+      listeners.push(listener);
+      return function () {
+        listeners = listeners.filter(function (l) { return l !== listener; });
+      };
+    },
+  };
+
+  window.requestAnimationFrame(frame);
+  return controller;
+}
@@ -1,16 +1,34 @@
-use board::Board;
+use board::{Anchor, BoolOp};
+#[cfg(not(target_arch = "wasm32"))]
+use export::{self, Recorder};
 use geom::{Move, Point, Size};
-use graphics;
+use grid::EdgeMode;
 use im::{ImageBuffer, Rgba};
-use opengl_graphics::{GlGraphics, Texture, TextureSettings};
-use piston::input::RenderArgs;
+use link::Link;
+use pattern::Pattern;
 use rand::Rng;
+use render::{self, Aggregate, Canvas, pixel_range};
+use session::Session;
+use shape::Tool;
 use std::cmp;
+use std::path::Path;
+use world::{RuleSpec, World};
+
+const MIN_SCALE: f64 = 1.0 / 256.0;
+const MAX_SCALE: f64 = 32.0;
+const ZOOM_SPEED: f64 = 12.0;
+
+const MINIMAP_SIZE: f64 = 192.0;
+const MINIMAP_MARGIN: i32 = 8;
+
+#[cfg(not(target_arch = "wasm32"))]
+const RECORD_DELAY: u16 = 5;
 
 pub struct AppSettings {
     pub win_size: Size,
     pub board_size: Size,
     pub rect_size: i32,
+    pub smooth_zoom: bool,
 }
 
 impl Default for AppSettings {
@@ -23,56 +41,113 @@ impl Default for AppSettings {
             win_size: win_size,
             board_size: board_size,
             rect_size: rect_size,
+            smooth_zoom: true,
         }
     }
 }
 
-pub struct App {
-    gl_graphics: GlGraphics,
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Touch {
+    Start,
+    Move,
+    End,
+    Cancel,
+}
 
+/// The viewer: the board together with the view onto it and the editing state.
+///
+/// The front ends feed it input and time, and show the canvas returned by `render` along with
+/// the lines from `hud_lines`.
+pub struct App {
     win_size: Size,
-    rect_size: i32,
+    scale: f64,
+    target_scale: f64,
+    zoom_anchor: (Point, (f64, f64)),
+    smooth_zoom: bool,
+    aggregate: Aggregate,
     offset: Move,
     slide: Move,
 
     running: bool,
+    generation: u64,
+    speed: f64,
+    speed_timer: f64,
+    speed_generation: u64,
+    show_hud: bool,
+    show_minimap: bool,
+    minimap_dragging: bool,
     mouse_pos: Point,
     onmouse_cell: Point,
 
     drawing: bool,
+    draw_state: u8,
     erasing: bool,
     moving: Option<(Point, Move)>,
+    tool: Tool,
+    constrain: bool,
+    shape: Option<(Point, bool)>,
+    touches: Vec<(i64, Point)>,
 
-    texture: Option<Texture>,
-    canvas: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    canvas: Option<Canvas>,
     invalidated: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    recording: Option<(Recorder, Size)>,
+
+    board: World,
+    // A copy of the board that runs alongside it, for showing where the two differ.
+    overlay: Option<World>,
+    // Where the board and the overlay differ, updated whenever either of them changes.
+    overlay_diff: Option<OverlayDiff>,
+}
 
-    board: Board,
+/// The cells alive on only one of the board and the overlay, and how many cells they share.
+struct OverlayDiff {
+    only_board: World,
+    only_overlay: World,
+    differ: u32,
+    shared: u32,
 }
 
 impl App {
-    pub fn new(settings: &AppSettings, gl_graphics: GlGraphics) -> Self {
+    pub fn new(settings: &AppSettings) -> Self {
         App {
-            gl_graphics: gl_graphics,
-
             win_size: settings.win_size,
-            rect_size: settings.rect_size,
+            scale: settings.rect_size as f64,
+            target_scale: settings.rect_size as f64,
+            zoom_anchor: (Point(0, 0), (0.0, 0.0)),
+            smooth_zoom: settings.smooth_zoom,
+            aggregate: Aggregate::AnyAlive,
             offset: Move(0, 0),
             slide: Move(0, 0),
 
             running: true,
+            generation: 0,
+            speed: 0.0,
+            speed_timer: 0.0,
+            speed_generation: 0,
+            show_hud: true,
+            show_minimap: true,
+            minimap_dragging: false,
             mouse_pos: Point(0, 0),
             onmouse_cell: Point(0, 0),
 
             drawing: false,
+            draw_state: 1,
             erasing: false,
             moving: None,
+            tool: Tool::Freehand,
+            constrain: false,
+            shape: None,
+            touches: vec![],
 
-            texture: None,
             canvas: None,
             invalidated: true,
+            #[cfg(not(target_arch = "wasm32"))]
+            recording: None,
 
-            board: Board::new_empty(settings.board_size),
+            board: World::new_empty(settings.board_size, RuleSpec::default()),
+            overlay: None,
+            overlay_diff: None,
         }
     }
 
@@ -80,66 +155,305 @@ impl App {
         where R: Rng
     {
         self.board.random_init(rng);
+        self.reset_generation();
+        self.update_overlay_diff();
         self.invalidated = true;
     }
 
     pub fn clear(&mut self) {
         self.board.clear();
+        self.reset_generation();
+        self.update_overlay_diff();
+        self.invalidated = true;
+    }
+
+    /// Replaces the board with `pattern`, centred on a board at least as large as the current one.
+    /// The pattern's rule is used if it has one, and a bounded grid in the rule sets the size.
+    pub fn load_pattern(&mut self, pattern: &Pattern) {
+        self.place_pattern(pattern);
+    }
+
+    /// Loads a pattern file dropped at `pos` in the window. A pattern with a rule other than the
+    /// board's replaces the board as with `load_pattern`, and so does one dropped outside the
+    /// board. Otherwise it is placed with its top-left corner on the cell it was dropped onto,
+    /// growing the board to fit.
+    pub fn drop_file(&mut self, path: &Path, pos: Point) {
+        let pattern = match Pattern::load(path) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                eprintln!("failed to load pattern: {}", e);
+                return;
+            }
+        };
+        let board_rule = self.board.rule().to_string();
+        let new_rule = pattern.rule().map_or(false, |rule| rule.to_string() != board_rule);
+        let cell = self.pos2cell(pos);
+        if new_rule || !self.board.contains(cell) {
+            self.load_pattern(&pattern);
+            return;
+        }
+
+        let Size(w, h) = self.board.size();
+        let size = Size(cmp::max(w, cell.0 + pattern.size().0),
+                        cmp::max(h, cell.1 + pattern.size().1));
+        self.board.resize(size, Anchor::TopLeft);
+        pattern.draw(&mut self.board, cell);
+        self.update_overlay_diff();
+        self.invalidated = true;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_pattern(&self) {
+        let path = export::timestamped_path("pattern", "rle");
+        match Pattern::from_world(&self.board).save(&path) {
+            Ok(()) => println!("saved {}", path.display()),
+            Err(e) => eprintln!("failed to save pattern: {}", e),
+        }
+    }
+
+    /// Describes the board and the view for a link to the web version.
+    pub fn link(&self) -> Link {
+        Link {
+            pattern: Pattern::from_world(&self.board),
+            rule: Some(self.board.rule()),
+            scale: Some(self.scale),
+            offset: Some(self.offset),
+        }
+    }
+
+    /// Loads the pattern of a link as with `load_pattern` and applies the view in it.
+    pub fn open_link(&mut self, link: &Link) {
+        let origin = self.place_pattern(&link.pattern);
+        if let Some(ref rule) = link.rule {
+            self.set_rule(rule.clone());
+        }
+        if let Some(scale) = link.scale {
+            self.scale = clamp_f64(scale, MIN_SCALE, MAX_SCALE);
+            self.target_scale = self.scale;
+        }
+        if let Some(offset) = link.offset {
+            self.offset = offset - Move(((origin.0 as f64) * self.scale).round() as i32,
+                                        ((origin.1 as f64) * self.scale).round() as i32);
+        }
+    }
+
+    /// Captures the board, the view and the settings.
+    pub fn session(&self) -> Session {
+        Session {
+            pattern: Pattern::from_world(&self.board),
+            origin: self.board.origin(),
+            edge: self.board.edge(),
+            max_size: self.board.max_size(),
+            generation: self.generation,
+            running: self.running,
+            offset: self.offset,
+            scale: self.scale,
+            aggregate: self.aggregate,
+            smooth_zoom: self.smooth_zoom,
+            show_hud: self.show_hud,
+            show_minimap: self.show_minimap,
+            draw_state: self.draw_state,
+        }
+    }
+
+    pub fn restore(&mut self, session: &Session) {
+        let pattern = &session.pattern;
+        let rule = pattern.rule().cloned().unwrap_or_else(|| self.board.rule());
+        let mut board = World::new_empty(pattern.size(), rule);
+        pattern.draw(&mut board, Point(0, 0));
+        if let Some(edge) = session.edge {
+            board.set_edge(edge);
+        }
+        board.set_max_size(session.max_size);
+        board.set_origin(session.origin);
+
+        self.board = board;
+        self.overlay = None;
+        self.overlay_diff = None;
+        self.reset_generation();
+        self.generation = session.generation;
+        self.running = session.running;
+        self.offset = session.offset;
+        self.scale = clamp_f64(session.scale, MIN_SCALE, MAX_SCALE);
+        self.target_scale = self.scale;
+        self.aggregate = session.aggregate;
+        self.smooth_zoom = session.smooth_zoom;
+        self.show_hud = session.show_hud;
+        self.show_minimap = session.show_minimap;
+        self.draw_state = if (session.draw_state as u32) < self.board.states() {
+            session.draw_state
+        } else {
+            1
+        };
+        self.invalidated = true;
+    }
+
+    pub fn save_session(&self) {
+        let result = Session::default_path()
+            .ok_or_else(|| "no config directory".to_string())
+            .and_then(|path| self.session().save(&path).map(|_| path));
+        match result {
+            Ok(path) => println!("saved {}", path.display()),
+            Err(e) => eprintln!("failed to save session: {}", e),
+        }
+    }
+
+    pub fn load_session(&mut self) {
+        let result = Session::default_path()
+            .ok_or_else(|| "no config directory".to_string())
+            .and_then(|path| Session::load(&path));
+        match result {
+            Ok(session) => self.restore(&session),
+            Err(e) => eprintln!("failed to load session: {}", e),
+        }
+    }
+
+    pub fn set_rule(&mut self, rule: RuleSpec) {
+        self.board.set_rule(rule);
+        self.draw_state = 1;
+        self.update_overlay_diff();
         self.invalidated = true;
     }
 
+    pub fn set_edge(&mut self, edge: EdgeMode) {
+        self.board.set_edge(edge);
+    }
+
+    pub fn set_max_size(&mut self, max_size: Option<Size>) {
+        self.board.set_max_size(max_size);
+    }
+
+    pub fn cycle_edge(&mut self) {
+        if let Some(edge) = self.board.edge() {
+            self.board.set_edge(edge.next());
+        }
+    }
+
     pub fn step(&mut self) {
         if !self.running {
-            self.board.grow();
-            self.invalidated = true;
+            self.grow();
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self, dt: f64) {
+        if self.scale != self.target_scale {
+            let scale = if self.smooth_zoom {
+                let t = 1.0 - (-ZOOM_SPEED * dt).exp();
+                let scale = (self.scale.ln() + (self.target_scale / self.scale).ln() * t).exp();
+                if (scale / self.target_scale).ln().abs() < 1e-3 {
+                    self.target_scale
+                } else {
+                    scale
+                }
+            } else {
+                self.target_scale
+            };
+            self.set_scale(scale);
+        }
+
         if self.slide != Move(0, 0) {
-            self.offset = self.offset -
-                          Move(self.slide.0 * self.rect_size, self.slide.1 * self.rect_size);
+            self.offset = self.offset - self.slide * cmp::max(self.scale.round() as i32, 1);
             self.invalidated = true;
         }
 
         if self.running {
-            self.board.grow();
-            self.invalidated = true;
+            self.grow();
+        }
+
+        self.speed_timer += dt;
+        if self.speed_timer >= 1.0 {
+            self.speed = ((self.generation - self.speed_generation) as f64) / self.speed_timer;
+            self.speed_timer = 0.0;
+            self.speed_generation = self.generation;
         }
     }
 
+    pub fn win_size(&self) -> Size {
+        self.win_size
+    }
+
     pub fn set_win_size(&mut self, size: Size) {
         if self.win_size != size {
             self.win_size = size;
             self.invalidated = true;
             self.canvas = None;
-            self.texture = None;
         }
     }
 
     pub fn fit_to_win_size(&mut self) {
-        let new_size = Size(self.win_size.0 / self.rect_size,
-                            self.win_size.1 / self.rect_size);
-        let mut board = Board::new_empty(new_size);
+        let new_size = Size(self.pixels2cells(self.win_size.0),
+                            self.pixels2cells(self.win_size.1));
+        self.board.resize(new_size, Anchor::Center);
+        self.update_overlay_diff();
+        self.invalidated = true;
+    }
 
-        let x_off = if board.size().0 > self.board.size().0 {
-            (board.size().0 - self.board.size().0) / 2
+    /// Grows or shrinks the board by a quarter, keeping the part of it under the mouse cursor
+    /// in place.
+    pub fn resize_board(&mut self, grow: bool) {
+        let Size(w, h) = self.board.size();
+        let size = if grow {
+            Size(w + (w + 3) / 4, h + (h + 3) / 4)
         } else {
-            0
+            Size(w - w / 4, h - h / 4)
         };
-        let y_off = if board.size().1 > self.board.size().1 {
-            (board.size().1 - self.board.size().1) / 2
-        } else {
-            0
+        let anchor = Anchor::at(self.onmouse_cell, self.board.size());
+        let shift = anchor.shift(self.board.size(), size);
+        self.board.resize(size, anchor);
+        if self.board.size() == size {
+            self.board_shifted(shift);
+        }
+        self.update_overlay_diff();
+    }
+
+    /// Starts running a copy of the board alongside it, showing the cells alive on only one of
+    /// them in distinct colors, or stops doing so.
+    pub fn toggle_overlay(&mut self) {
+        self.overlay = match self.overlay {
+            Some(_) => None,
+            None => Some(self.board.clone()),
         };
+        self.update_overlay_diff();
+        self.invalidated = true;
+    }
+
+    /// Returns where the top-left cell of `overlay` lies on the board.
+    fn overlay_offset(&self, overlay: &World) -> Point {
+        let (board, overlay) = (self.board.origin(), overlay.origin());
+        Point(overlay.0 - board.0, overlay.1 - board.1)
+    }
 
-        for x in 0..cmp::min(board.size().0, self.board.size().0) {
-            for y in 0..cmp::min(board.size().1, self.board.size().1) {
-                board.set(Point(x + x_off, y + y_off), self.board.get(Point(x, y)));
+    /// Recomputes where the board and the overlay differ, after either of them has changed.
+    fn update_overlay_diff(&mut self) {
+        let diff = self.overlay.as_ref().map(|overlay| {
+            let offset = self.overlay_offset(overlay);
+            let mut only_board = self.board.clone();
+            only_board.combine(overlay, offset, BoolOp::Difference);
+            let mut only_overlay = World::new_empty(self.board.size(), self.board.rule());
+            only_overlay.combine(overlay, offset, BoolOp::Or);
+            only_overlay.combine(&self.board, Point(0, 0), BoolOp::Difference);
+            OverlayDiff {
+                differ: only_board.population() + only_overlay.population(),
+                shared: self.board.count_combined(overlay, offset, BoolOp::And),
+                only_board: only_board,
+                only_overlay: only_overlay,
             }
-        }
+        });
+        self.overlay_diff = diff;
+    }
 
-        self.board = board;
+    /// Applies one of the `World` transformations, keeping cells whose world coordinates stay
+    /// the same in place on the screen.
+    pub fn transform<F>(&mut self, f: F)
+        where F: FnOnce(&mut World)
+    {
+        let origin = self.board.origin();
+        f(&mut self.board);
+        let shift = origin - self.board.origin();
+        if shift != Move(0, 0) {
+            self.board_shifted(shift);
+        }
+        self.update_overlay_diff();
         self.invalidated = true;
     }
 
@@ -147,6 +461,62 @@ impl App {
         self.running = !self.running;
     }
 
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn population(&self) -> u32 {
+        self.board.population()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_screenshot(&self) {
+        let canvas = self.render_view(self.win_size);
+        save_png(&canvas, &export::timestamped_path("screenshot", "png"));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_board_image(&self) {
+        let canvas = render::render_board(&self.board, self.scale, self.aggregate);
+        save_png(&canvas, &export::timestamped_path("board", "png"));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn toggle_gif_recording(&mut self) {
+        self.toggle_recording("gif");
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn toggle_png_recording(&mut self) {
+        self.toggle_recording("png");
+    }
+
+    pub fn toggle_hud(&mut self) {
+        self.show_hud = !self.show_hud;
+    }
+
+    pub fn toggle_minimap(&mut self) {
+        self.show_minimap = !self.show_minimap;
+        self.minimap_dragging = false;
+        self.invalidated = true;
+    }
+
+    pub fn toggle_smooth_zoom(&mut self) {
+        self.smooth_zoom = !self.smooth_zoom;
+    }
+
+    pub fn toggle_aggregate(&mut self) {
+        self.aggregate = match self.aggregate {
+            Aggregate::AnyAlive => Aggregate::Density,
+            Aggregate::Density => Aggregate::AnyAlive,
+        };
+        self.invalidated = true;
+    }
+
     pub fn mouse_move(&mut self, mouse_pos: Point) {
         self.mouse_pos = mouse_pos;
 
@@ -156,12 +526,22 @@ impl App {
 
         if let Some((start_pos, start_offset)) = self.moving {
             self.offset = start_offset + (mouse_pos - start_pos);
+            self.invalidated = true;
+        }
+
+        if self.minimap_dragging {
+            self.jump_to_minimap(mouse_pos);
+            return;
+        }
+
+        if self.shape.is_some() && onmouse_cell != old_cell {
+            self.invalidated = true;
         }
 
         let op = match (self.drawing, self.erasing) {
             (true, true) => None,
-            (true, false) => Some(true),
-            (false, true) => Some(false),
+            (true, false) => Some(self.draw_state),
+            (false, true) => Some(0),
             (false, false) => None,
         };
 
@@ -173,37 +553,49 @@ impl App {
             for i in 0..a {
                 let p = old_cell + Move(diff.0 * i / a, diff.1 * i / a);
                 if self.board.contains(p) {
-                    self.board.set(p, val);
+                    self.board.set_state(p, val);
                 }
             }
             if self.board.contains(onmouse_cell) {
-                self.board.set(onmouse_cell, val);
+                self.board.set_state(onmouse_cell, val);
             }
+            self.update_overlay_diff();
             self.invalidated = true;
         }
     }
 
-    pub fn zoom(&mut self, scale: i32) {
-        let pos0 = ((self.mouse_pos.0 - self.offset.0) as f64) / (self.rect_size as f64);
-        let pos1 = ((self.mouse_pos.1 - self.offset.1) as f64) / (self.rect_size as f64);
+    /// Zooms around the mouse position. Each step doubles or halves the cell size.
+    pub fn zoom(&mut self, steps: f64) {
+        let anchor = self.mouse_pos;
+        let scale = self.target_scale * 2f64.powf(steps);
+        let animate = self.smooth_zoom;
+        self.zoom_to(scale, anchor, animate);
+    }
 
-        if scale > 0 {
-            for _ in 0..scale.abs() {
-                if self.rect_size < 32 {
-                    self.rect_size *= 2;
-                }
-            }
-        } else {
-            for _ in 0..scale.abs() {
-                if self.rect_size > 1 {
-                    self.rect_size /= 2;
+    pub fn touch(&mut self, id: i64, pos: [f64; 2], touch: Touch) {
+        let pos = Point((pos[0] * (self.win_size.0 as f64)) as i32,
+                        (pos[1] * (self.win_size.1 as f64)) as i32);
+        let old_pinch = self.pinch();
+
+        match touch {
+            Touch::Start => self.touches.push((id, pos)),
+            Touch::Move => {
+                if let Some(t) = self.touches.iter_mut().find(|t| t.0 == id) {
+                    t.1 = pos;
                 }
             }
+            Touch::End | Touch::Cancel => self.touches.retain(|t| t.0 != id),
         }
 
-        self.offset = Move(((self.mouse_pos.0 as f64) - pos0 * (self.rect_size as f64)) as i32,
-                           ((self.mouse_pos.1 as f64) - pos1 * (self.rect_size as f64)) as i32);
-        self.invalidated = true;
+        // Two fingers drag the view along with their midpoint and zoom with the distance between
+        // them.
+        if let (Some((old_center, old_dist)), Some((center, dist))) = (old_pinch, self.pinch()) {
+            if touch == Touch::Move && old_dist > 0.0 {
+                self.offset = self.offset + (center - old_center);
+                let scale = self.scale * dist / old_dist;
+                self.zoom_to(scale, center, false);
+            }
+        }
     }
 
     pub fn slide(&mut self, mv: Move) {
@@ -211,15 +603,51 @@ impl App {
     }
 
     pub fn drawing(&mut self, val: bool) {
-        self.drawing = val;
+        if val && self.minimap_rect().map_or(false, |r| r.contains(self.mouse_pos)) {
+            self.minimap_dragging = true;
+        }
+        if !val {
+            self.minimap_dragging = false;
+        }
+
+        if self.tool != Tool::Freehand {
+            let pressed = val && !self.minimap_dragging;
+            self.shape_button(pressed, true);
+            return;
+        }
+
+        self.drawing = val && !self.minimap_dragging;
+        if self.drawing {
+            self.pick_draw_state();
+        }
         let mouse_pos = self.mouse_pos;
         self.mouse_move(mouse_pos);
     }
     pub fn erasing(&mut self, val: bool) {
+        if self.tool != Tool::Freehand {
+            self.shape_button(val, false);
+            return;
+        }
+
         self.erasing = val;
         let mouse_pos = self.mouse_pos;
         self.mouse_move(mouse_pos);
     }
+    pub fn select_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+        self.drawing = false;
+        self.erasing = false;
+        self.shape = None;
+        self.invalidated = true;
+    }
+
+    pub fn constrain(&mut self, val: bool) {
+        self.constrain = val;
+        if self.shape.is_some() {
+            self.invalidated = true;
+        }
+    }
+
     pub fn moving(&mut self, val: bool) {
         if val {
             self.moving = Some((self.mouse_pos, self.offset));
@@ -228,19 +656,264 @@ impl App {
         }
     }
 
-    pub fn draw(&mut self, args: RenderArgs) {
+    /// Redraws the view if anything changed since the last call, and returns it in that case.
+    pub fn render(&mut self) -> Option<&Canvas> {
         self.adjust_offset();
-        self.update_texture();
-        let texture = self.texture.take().unwrap();
+        if self.update_canvas() {
+            self.canvas.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Does the work of `load_pattern`, returning where the top-left corner of the pattern went.
+    fn place_pattern(&mut self, pattern: &Pattern) -> Point {
+        let old_size = self.board.size();
+        let size = Size(cmp::max(old_size.0, pattern.size().0),
+                        cmp::max(old_size.1, pattern.size().1));
+        let rule = pattern.rule().cloned().unwrap_or_else(|| self.board.rule());
+        let mut board = World::new_empty(size, rule);
+        board.inherit(&self.board);
+        let size = board.size();
+        let origin = Point((size.0 - pattern.size().0) / 2, (size.1 - pattern.size().1) / 2);
+        pattern.draw(&mut board, origin);
+
+        self.board = board;
+        self.draw_state = 1;
+        self.reset_generation();
+        self.update_overlay_diff();
+        self.invalidated = true;
+        origin
+    }
+
+    fn grow(&mut self) {
+        let origin = self.board.origin();
+        self.board.grow();
+        if let Some(ref mut overlay) = self.overlay {
+            overlay.grow();
+        }
+        self.update_overlay_diff();
+        let shift = origin - self.board.origin();
+        if shift != Move(0, 0) {
+            self.board_shifted(shift);
+        }
+        self.generation += 1;
+        self.invalidated = true;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.record_frame();
+    }
+
+    /// Keeps the view and the cells being edited in place after the board contents move by
+    /// `shift` cells.
+    fn board_shifted(&mut self, shift: Move) {
+        let pixels = Move(((shift.0 as f64) * self.scale).round() as i32,
+                          ((shift.1 as f64) * self.scale).round() as i32);
+        self.offset = self.offset - pixels;
+        if let Some((pos, offset)) = self.moving {
+            self.moving = Some((pos, offset - pixels));
+        }
+        if let Some((start, value)) = self.shape {
+            self.shape = Some((start + shift, value));
+        }
+        self.onmouse_cell = self.onmouse_cell + shift;
+        self.invalidated = true;
+    }
+
+    /// Starts a shape on button press and commits it to the board on release.
+    fn shape_button(&mut self, pressed: bool, value: bool) {
+        if pressed {
+            if self.shape.is_none() {
+                self.shape = Some((self.onmouse_cell, value));
+            }
+            return;
+        }
+
+        if let Some((start, shape_value)) = self.shape {
+            if shape_value == value {
+                for p in self.tool.cells(start, self.onmouse_cell, self.constrain) {
+                    if self.board.contains(p) {
+                        let state = if value { self.draw_state } else { 0 };
+                        self.board.set_state(p, state);
+                    }
+                }
+                self.shape = None;
+                self.update_overlay_diff();
+                self.invalidated = true;
+            }
+        }
+    }
+
+    /// With more than two states, a click advances the state of the cell under the mouse and a
+    /// drag paints that state. Continuous worlds are drawn at full value.
+    fn pick_draw_state(&mut self) {
+        let states = self.board.states();
+        if self.board.is_continuous() {
+            self.draw_state = 255;
+        } else if states <= 2 {
+            self.draw_state = 1;
+        } else if self.board.contains(self.onmouse_cell) {
+            let state = self.board.state(self.onmouse_cell) as u32;
+            self.draw_state = ((state + 1) % states) as u8;
+        }
+    }
+
+    fn draw_shape_preview(&self, canvas: &mut Canvas, start: Point, value: bool) {
+        let color = if value {
+            Rgba([0, 255, 0, 255])
+        } else {
+            Rgba([255, 0, 0, 255])
+        };
+        let (width, height) = canvas.dimensions();
+        let range = |cell: i32, offset: i32| {
+            let start = offset + ((cell as f64) * self.scale).floor() as i32;
+            let end = offset + (((cell + 1) as f64) * self.scale).floor() as i32;
+            (start, cmp::max(end, start + 1))
+        };
+
+        let shift = render::odd_row_shift(&self.board, self.scale);
+        for p in self.tool.cells(start, self.onmouse_cell, self.constrain) {
+            let shift = if p.1 % 2 != 0 { shift } else { 0 };
+            let (x0, x1) = range(p.0, self.offset.0 + shift);
+            let (y0, y1) = range(p.1, self.offset.1);
+            for x in cmp::max(x0, 0)..cmp::min(x1, width as i32) {
+                for y in cmp::max(y0, 0)..cmp::min(y1, height as i32) {
+                    canvas.put_pixel(x as u32, y as u32, color);
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn toggle_recording(&mut self, ext: &str) {
+        if let Some((recorder, _)) = self.recording.take() {
+            println!("saved {}", recorder.path().display());
+            return;
+        }
+
+        let path = export::timestamped_path("recording", ext);
+        match Recorder::new(path.clone(), RECORD_DELAY) {
+            Ok(recorder) => {
+                self.recording = Some((recorder, self.win_size));
+                self.record_frame();
+            }
+            Err(e) => eprintln!("failed to record {}: {}", path.display(), e),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn record_frame(&mut self) {
+        if let Some((mut recorder, size)) = self.recording.take() {
+            let canvas = self.render_view(size);
+            match recorder.add_frame(&canvas) {
+                Ok(()) => self.recording = Some((recorder, size)),
+                Err(e) => eprintln!("failed to record {}: {}", recorder.path().display(), e),
+            }
+        }
+    }
+
+    /// Renders the cells in the current view without overlays.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn render_view(&self, size: Size) -> Canvas {
+        let mut canvas = ImageBuffer::new(size.0 as u32, size.1 as u32);
+        render::render_cells(&mut canvas, &self.board, self.offset, self.scale, self.aggregate);
+        canvas
+    }
+
+    fn reset_generation(&mut self) {
+        self.generation = 0;
+        self.speed_generation = 0;
+        self.speed_timer = 0.0;
+        self.speed = 0.0;
+    }
+
+    /// Returns the lines of the heads-up display, or none if it is hidden.
+    pub fn hud_lines(&self) -> Vec<String> {
+        if !self.show_hud {
+            return vec![];
+        }
 
-        self.gl_graphics.draw(args.viewport(),
-                              |ctx, g2d| graphics::image(&texture, ctx.transform, g2d));
-        self.texture = Some(texture);
+        let state = if self.running { "running" } else { "paused" };
+        let zoom = if self.scale >= 1.0 {
+            format!("{:.2} px/cell", self.scale)
+        } else {
+            format!("{:.2} cells/px", 1.0 / self.scale)
+        };
+        let cursor = if self.board.contains(self.onmouse_cell) {
+            let origin = self.board.origin();
+            format!("({}, {})",
+                    origin.0 + self.onmouse_cell.0,
+                    origin.1 + self.onmouse_cell.1)
+        } else {
+            "-".to_string()
+        };
+
+        let mut lines = vec![format!("Generation: {} ({})", self.generation, state),
+                             format!("Population: {}", self.board.population()),
+                             format!("Speed:      {:.1} gen/s", self.speed),
+                             format!("Zoom:       {}", zoom),
+                             format!("Cursor:     {}", cursor),
+                             format!("Tool:       {}", self.tool.name()),
+                             format!("Rule:       {}", self.board.rule())];
+        if let Some(edge) = self.board.edge() {
+            lines.push(format!("Edges:      {}", edge));
+        }
+        if self.board.states() > 2 && !self.board.is_continuous() {
+            lines.push(format!("Drawing:    state {}", self.draw_state));
+        }
+        if let Some(ref diff) = self.overlay_diff {
+            lines.push(format!("Overlay:    {} cells differ, {} shared",
+                               diff.differ,
+                               diff.shared));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some((ref recorder, _)) = self.recording {
+                lines.push(format!("Recording:  {}", recorder.path().display()));
+            }
+        }
+        lines
+    }
+
+    fn zoom_to(&mut self, scale: f64, anchor: Point, animate: bool) {
+        let cell = (((anchor.0 - self.offset.0) as f64) / self.scale,
+                    ((anchor.1 - self.offset.1) as f64) / self.scale);
+        self.zoom_anchor = (anchor, cell);
+        self.target_scale = clamp_f64(scale, MIN_SCALE, MAX_SCALE);
+        if !animate {
+            let scale = self.target_scale;
+            self.set_scale(scale);
+        }
+    }
+
+    fn set_scale(&mut self, scale: f64) {
+        let (anchor, cell) = self.zoom_anchor;
+        self.scale = scale;
+        self.offset = Move(((anchor.0 as f64) - cell.0 * scale).round() as i32,
+                           ((anchor.1 as f64) - cell.1 * scale).round() as i32);
+        self.invalidated = true;
+    }
+
+    fn pinch(&self) -> Option<(Point, f64)> {
+        if self.touches.len() != 2 {
+            return None;
+        }
+        let (p0, p1) = (self.touches[0].1, self.touches[1].1);
+        let center = Point((p0.0 + p1.0) / 2, (p0.1 + p1.1) / 2);
+        let d = p1 - p0;
+        Some((center, ((d.0 * d.0 + d.1 * d.1) as f64).sqrt()))
     }
 
     fn board_size(&self) -> Size {
-        Size(self.rect_size * self.board.size().0,
-             self.rect_size * self.board.size().1)
+        Size(self.cells2pixels(self.board.size().0),
+             self.cells2pixels(self.board.size().1))
+    }
+
+    fn cells2pixels(&self, n: i32) -> i32 {
+        ((n as f64) * self.scale).ceil() as i32
+    }
+
+    fn pixels2cells(&self, n: i32) -> i32 {
+        ((n as f64) / self.scale).floor() as i32
     }
 
     fn adjust_offset(&mut self) {
@@ -259,48 +932,153 @@ impl App {
         };
     }
 
-    fn update_texture(&mut self) {
-        if self.canvas.is_none() || self.texture.is_none() {
+    /// Redraws the canvas if it is out of date, returning whether it was.
+    fn update_canvas(&mut self) -> bool {
+        if self.canvas.is_none() {
             let canvas = ImageBuffer::new(self.win_size.0 as u32, self.win_size.1 as u32);
-            let texture = Texture::from_image(&canvas, &TextureSettings::new());
             self.canvas = Some(canvas);
-            self.texture = Some(texture);
             self.invalidated = true;
         }
 
-        if self.invalidated {
-            self.invalidated = false;
+        if !self.invalidated {
+            return false;
+        }
+        self.invalidated = false;
+
+        let mut canvas = self.canvas.take().unwrap();
 
-            let mut canvas = self.canvas.take().unwrap();
-            let mut texture = self.texture.take().unwrap();
+        render::render_cells(&mut canvas, &self.board, self.offset, self.scale, self.aggregate);
 
-            for wx in 0..self.win_size.0 {
-                for wy in 0..self.win_size.1 {
-                    let p = self.pos2cell(Point(wx, wy));
-                    let color = if !self.board.contains(p) {
-                        [128, 128, 128, 255]
-                    } else if self.board.get(p) {
-                        [255, 255, 255, 255]
-                    } else {
-                        [0, 0, 0, 255]
-                    };
-                    canvas.put_pixel(wx as u32, wy as u32, Rgba(color));
-                }
+        if let Some(ref diff) = self.overlay_diff {
+            render::render_differences(&mut canvas,
+                                       &diff.only_board,
+                                       &diff.only_overlay,
+                                       self.offset,
+                                       self.scale);
+        }
+
+        if let Some((start, value)) = self.shape {
+            self.draw_shape_preview(&mut canvas, start, value);
+        }
+
+        if let Some(rect) = self.minimap_rect() {
+            self.draw_minimap(&mut canvas, rect);
+        }
+
+        self.canvas = Some(canvas);
+        true
+    }
+
+    /// Returns the position of the overview panel in the bottom-right corner of the window,
+    /// or `None` if it is hidden or does not fit.
+    fn minimap_rect(&self) -> Option<Rect> {
+        if !self.show_minimap {
+            return None;
+        }
+
+        let board_size = self.board.size();
+        let scale = self.minimap_scale();
+        let size = Size(((board_size.0 as f64) * scale).ceil() as i32,
+                        ((board_size.1 as f64) * scale).ceil() as i32);
+        let origin = Point(self.win_size.0 - size.0 - MINIMAP_MARGIN,
+                           self.win_size.1 - size.1 - MINIMAP_MARGIN);
+        if origin.0 < MINIMAP_MARGIN || origin.1 < MINIMAP_MARGIN {
+            return None;
+        }
+        Some(Rect(origin, size))
+    }
+
+    fn minimap_scale(&self) -> f64 {
+        let board_size = self.board.size();
+        MINIMAP_SIZE / (cmp::max(cmp::max(board_size.0, board_size.1), 1) as f64)
+    }
+
+    fn draw_minimap(&self, canvas: &mut Canvas, rect: Rect) {
+        let Rect(origin, size) = rect;
+        let scale = self.minimap_scale();
+
+        for mx in 0..size.0 {
+            let (x, w) = pixel_range(mx, scale);
+            for my in 0..size.1 {
+                let (y, h) = pixel_range(my, scale);
+                let color =
+                    render::pixel_color(&self.board, self.aggregate, Point(x, y), Size(w, h));
+                canvas.put_pixel((origin.0 + mx) as u32, (origin.1 + my) as u32, Rgba(color));
             }
+        }
 
-            texture.update(&canvas);
+        let border = Rect(Point(origin.0 - 1, origin.1 - 1), Size(size.0 + 2, size.1 + 2));
+        draw_outline(canvas, border, Rect(Point(0, 0), self.win_size), [128, 128, 128, 255]);
 
-            self.canvas = Some(canvas);
-            self.texture = Some(texture);
+        let view_origin = self.pos2cell(Point(0, 0));
+        let view_end = self.pos2cell(Point(self.win_size.0, self.win_size.1));
+        let view = Rect(Point(origin.0 + ((view_origin.0 as f64) * scale) as i32,
+                              origin.1 + ((view_origin.1 as f64) * scale) as i32),
+                        Size((((view_end.0 - view_origin.0) as f64) * scale).ceil() as i32,
+                             (((view_end.1 - view_origin.1) as f64) * scale).ceil() as i32));
+        draw_outline(canvas, view, rect, [255, 0, 0, 255]);
+    }
+
+    /// Scrolls the view so that the cell under `pos` on the overview panel is centered.
+    fn jump_to_minimap(&mut self, pos: Point) {
+        if let Some(Rect(origin, _)) = self.minimap_rect() {
+            let scale = self.minimap_scale();
+            let cell = (((pos.0 - origin.0) as f64) / scale, ((pos.1 - origin.1) as f64) / scale);
+            self.offset = Move(self.win_size.0 / 2 - (cell.0 * self.scale) as i32,
+                               self.win_size.1 / 2 - (cell.1 * self.scale) as i32);
+            self.invalidated = true;
         }
     }
 
     fn pos2cell(&self, pos: Point) -> Point {
-        Point((pos.0 - self.offset.0) / self.rect_size,
-              (pos.1 - self.offset.1) / self.rect_size)
+        let y = self.pixels2cells(pos.1 - self.offset.1);
+        let shift = if y % 2 != 0 {
+            render::odd_row_shift(&self.board, self.scale)
+        } else {
+            0
+        };
+        Point(self.pixels2cells(pos.0 - self.offset.0 - shift), y)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Rect(Point, Size);
+
+impl Rect {
+    fn contains(&self, p: Point) -> bool {
+        let Rect(origin, size) = *self;
+        origin.0 <= p.0 && p.0 < origin.0 + size.0 && origin.1 <= p.1 && p.1 < origin.1 + size.1
+    }
+}
+
+/// Draws the outline of `rect`, skipping the pixels outside of `clip`.
+fn draw_outline(canvas: &mut Canvas, rect: Rect, clip: Rect, color: [u8; 4]) {
+    let Rect(origin, size) = rect;
+    let mut put = |x: i32, y: i32| if clip.contains(Point(x, y)) {
+        canvas.put_pixel(x as u32, y as u32, Rgba(color));
+    };
+    for x in origin.0..(origin.0 + size.0) {
+        put(x, origin.1);
+        put(x, origin.1 + size.1 - 1);
+    }
+    for y in origin.1..(origin.1 + size.1) {
+        put(origin.0, y);
+        put(origin.0 + size.0 - 1, y);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_png(canvas: &Canvas, path: &Path) {
+    match export::save_png(canvas, path) {
+        Ok(()) => println!("saved {}", path.display()),
+        Err(e) => eprintln!("failed to save {}: {}", path.display(), e),
     }
 }
 
 fn clamp(val: i32, min: i32, max: i32) -> i32 {
     cmp::min(cmp::max(val, min), max)
 }
+
+fn clamp_f64(val: f64, min: f64, max: f64) -> f64 {
+    val.max(min).min(max)
+}
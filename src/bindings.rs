@@ -0,0 +1,345 @@
+use piston::input::{Button, Key, MouseButton};
+use piston::input::keyboard::{ALT, CTRL, GUI, ModifierKey, NO_MODIFIER, SHIFT};
+use session;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Action {
+    RandomInit,
+    Clear,
+    ToggleRunning,
+    FitToWindow,
+    Step,
+    ZoomIn,
+    ZoomOut,
+    ScrollRight,
+    ScrollLeft,
+    ScrollUp,
+    ScrollDown,
+    ToggleDensity,
+    ToggleSmoothZoom,
+    ToggleHud,
+    ToggleMinimap,
+    CycleEdge,
+    ToggleOverlay,
+    SaveSession,
+    LoadSession,
+    RotateClockwise,
+    RotateCounterclockwise,
+    Rotate180,
+    FlipHorizontal,
+    FlipVertical,
+    TranslateRight,
+    TranslateLeft,
+    TranslateUp,
+    TranslateDown,
+    Crop,
+    GrowBoard,
+    ShrinkBoard,
+    Screenshot,
+    SaveBoardImage,
+    SavePattern,
+    RecordGif,
+    RecordPng,
+    ToolFreehand,
+    ToolLine,
+    ToolRectangle,
+    ToolFilledRectangle,
+    ToolEllipse,
+    ToolFilledEllipse,
+    Constrain,
+    Draw,
+    Erase,
+    Move,
+}
+
+/// Action names used in the bindings file, with the default triggers.
+const ACTIONS: &'static [(Action, &'static str, &'static str)] = &[
+    (Action::RandomInit, "random-init", "R"),
+    (Action::Clear, "clear", "C"),
+    (Action::ToggleRunning, "toggle-running", "S"),
+    (Action::FitToWindow, "fit-to-window", "F"),
+    (Action::Step, "step", "Space"),
+    (Action::ZoomIn, "zoom-in", "Equals"),
+    (Action::ZoomOut, "zoom-out", "Minus"),
+    (Action::ScrollRight, "scroll-right", "Right"),
+    (Action::ScrollLeft, "scroll-left", "Left"),
+    (Action::ScrollUp, "scroll-up", "Up"),
+    (Action::ScrollDown, "scroll-down", "Down"),
+    (Action::ToggleDensity, "toggle-density", "D"),
+    (Action::ToggleSmoothZoom, "toggle-smooth-zoom", "Z"),
+    (Action::ToggleHud, "toggle-hud", "H"),
+    (Action::ToggleMinimap, "toggle-minimap", "M"),
+    (Action::CycleEdge, "cycle-edge", "E"),
+    (Action::ToggleOverlay, "toggle-overlay", "O"),
+    (Action::RotateClockwise, "rotate-clockwise", "Ctrl+R"),
+    (Action::RotateCounterclockwise, "rotate-counterclockwise", "Ctrl+Shift+R"),
+    (Action::Rotate180, "rotate-180", "Ctrl+U"),
+    (Action::FlipHorizontal, "flip-horizontal", "Ctrl+H"),
+    (Action::FlipVertical, "flip-vertical", "Ctrl+V"),
+    (Action::TranslateRight, "translate-right", "Alt+Right"),
+    (Action::TranslateLeft, "translate-left", "Alt+Left"),
+    (Action::TranslateUp, "translate-up", "Alt+Up"),
+    (Action::TranslateDown, "translate-down", "Alt+Down"),
+    (Action::Crop, "crop", "X"),
+    (Action::GrowBoard, "grow-board", "Ctrl+Equals"),
+    (Action::ShrinkBoard, "shrink-board", "Ctrl+Minus"),
+    (Action::Screenshot, "screenshot", "P"),
+    (Action::SaveBoardImage, "save-board-image", "B"),
+    (Action::SavePattern, "save-pattern", "W"),
+    (Action::SaveSession, "save-session", "Ctrl+S"),
+    (Action::LoadSession, "load-session", "Ctrl+L"),
+    (Action::RecordGif, "record-gif", "V"),
+    (Action::RecordPng, "record-png", "N"),
+    (Action::ToolFreehand, "tool-freehand", "D1"),
+    (Action::ToolLine, "tool-line", "D2"),
+    (Action::ToolRectangle, "tool-rectangle", "D3"),
+    (Action::ToolFilledRectangle, "tool-filled-rectangle", "D4"),
+    (Action::ToolEllipse, "tool-ellipse", "D5"),
+    (Action::ToolFilledEllipse, "tool-filled-ellipse", "D6"),
+    (Action::Constrain, "constrain", "LShift, RShift"),
+    (Action::Draw, "draw", "MouseLeft"),
+    (Action::Erase, "erase", "MouseRight"),
+    (Action::Move, "move", "MouseMiddle"),
+];
+
+const MODIFIERS: &'static [(&'static str, ModifierKey)] =
+    &[("Ctrl", CTRL), ("Shift", SHIFT), ("Alt", ALT), ("Gui", GUI)];
+
+const MOUSE_BUTTONS: &'static [(&'static str, MouseButton)] = &[
+    ("MouseLeft", MouseButton::Left),
+    ("MouseRight", MouseButton::Right),
+    ("MouseMiddle", MouseButton::Middle),
+    ("MouseX1", MouseButton::X1),
+    ("MouseX2", MouseButton::X2),
+    ("MouseButton6", MouseButton::Button6),
+    ("MouseButton7", MouseButton::Button7),
+    ("MouseButton8", MouseButton::Button8),
+];
+
+/// A button together with the modifier keys that must be held.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Trigger {
+    pub button: Button,
+    pub modifiers: ModifierKey,
+}
+
+/// Maps buttons to actions.
+///
+/// The bindings file consists of lines like `zoom-in = Equals, Ctrl+Up` (see README.md).
+/// Actions listed in the file replace their default triggers; the others keep the defaults.
+pub struct Bindings {
+    table: HashMap<Trigger, Action>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Bindings { table: HashMap::new() };
+        for &(action, _, triggers) in ACTIONS {
+            for trigger in parse_triggers(triggers).expect("invalid default bindings") {
+                bindings.table.insert(trigger, action);
+            }
+        }
+        bindings
+    }
+}
+
+impl Bindings {
+    /// Returns the default location of the bindings file.
+    pub fn default_path() -> Option<PathBuf> {
+        session::config_dir().map(|dir| dir.join("bindings.conf"))
+    }
+
+    /// Loads the bindings file at the default location, if any.
+    pub fn load() -> Result<Bindings, String> {
+        let path = match Bindings::default_path() {
+            Some(path) => path,
+            None => return Ok(Bindings::default()),
+        };
+
+        let mut content = String::new();
+        match File::open(&path).and_then(|mut f| f.read_to_string(&mut content)) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Bindings::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        }
+        Bindings::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings::default();
+
+        for (i, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut it = line.splitn(2, '=');
+            let (name, triggers) = match (it.next(), it.next()) {
+                (Some(name), Some(triggers)) => (name.trim(), triggers),
+                _ => return Err(format!("line {}: expected `action = triggers`", i + 1)),
+            };
+            let action = match ACTIONS.iter().find(|a| a.1 == name) {
+                Some(a) => a.0,
+                None => return Err(format!("line {}: unknown action `{}`", i + 1, name)),
+            };
+            let triggers = parse_triggers(triggers).map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+            bindings.table.retain(|_, a| *a != action);
+            for trigger in triggers {
+                bindings.table.insert(trigger, action);
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    /// Returns the action bound to `button` under the held modifier keys.
+    ///
+    /// A modifier key does not count as modifying its own press. A mouse button bound without
+    /// modifiers still applies while Shift is held, so that holding Shift to constrain a shape
+    /// does not disable drawing with the mouse.
+    pub fn lookup(&self, button: Button, modifiers: ModifierKey) -> Option<Action> {
+        let modifiers = modifiers - own_modifier(button);
+        let exact = Trigger {
+            button: button,
+            modifiers: modifiers,
+        };
+        if let Some(&action) = self.table.get(&exact) {
+            return Some(action);
+        }
+        match button {
+            Button::Mouse(_) if modifiers == SHIFT => {
+                let plain = Trigger {
+                    button: button,
+                    modifiers: NO_MODIFIER,
+                };
+                self.table.get(&plain).cloned()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the modifier that `button` itself sets, if it is a modifier key.
+fn own_modifier(button: Button) -> ModifierKey {
+    match button {
+        Button::Keyboard(Key::LCtrl) | Button::Keyboard(Key::RCtrl) => CTRL,
+        Button::Keyboard(Key::LShift) | Button::Keyboard(Key::RShift) => SHIFT,
+        Button::Keyboard(Key::LAlt) | Button::Keyboard(Key::RAlt) => ALT,
+        Button::Keyboard(Key::LGui) | Button::Keyboard(Key::RGui) => GUI,
+        _ => NO_MODIFIER,
+    }
+}
+
+fn parse_triggers(s: &str) -> Result<Vec<Trigger>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(parse_trigger)
+        .collect()
+}
+
+fn parse_trigger(s: &str) -> Result<Trigger, String> {
+    let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+    let name = parts.pop().unwrap();
+
+    let mut modifiers = NO_MODIFIER;
+    for part in parts {
+        match MODIFIERS.iter().find(|m| m.0.eq_ignore_ascii_case(part)) {
+            Some(m) => modifiers.insert(m.1),
+            None => return Err(format!("unknown modifier `{}`", part)),
+        }
+    }
+
+    let button = match parse_button(name) {
+        Some(button) => button,
+        None => return Err(format!("unknown key or button `{}`", name)),
+    };
+    Ok(Trigger {
+        button: button,
+        modifiers: modifiers,
+    })
+}
+
+fn parse_button(name: &str) -> Option<Button> {
+    if let Some(m) = MOUSE_BUTTONS.iter().find(|m| m.0.eq_ignore_ascii_case(name)) {
+        return Some(Button::Mouse(m.1));
+    }
+
+    // `Key` has no parser, so match against the variant names of all known key codes.
+    (0x00..0x80)
+        .chain(0x40000039..0x40000120)
+        .map(Key::from)
+        .filter(|&key| key != Key::Unknown)
+        .find(|key| format!("{:?}", key).eq_ignore_ascii_case(name))
+        .map(Button::Keyboard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: Key) -> Button {
+        Button::Keyboard(key)
+    }
+
+    #[test]
+    fn defaults() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.lookup(key(Key::R), NO_MODIFIER), Some(Action::RandomInit));
+        assert_eq!(bindings.lookup(key(Key::R), CTRL), Some(Action::RotateClockwise));
+        assert_eq!(bindings.lookup(key(Key::R), CTRL | SHIFT),
+                   Some(Action::RotateCounterclockwise));
+        assert_eq!(bindings.lookup(key(Key::C), CTRL), None);
+        assert_eq!(bindings.lookup(key(Key::C), ALT), None);
+        assert_eq!(bindings.lookup(key(Key::R), SHIFT), None);
+    }
+
+    #[test]
+    fn modifier_fallbacks() {
+        let bindings = Bindings::default();
+        let left = Button::Mouse(MouseButton::Left);
+        assert_eq!(bindings.lookup(left, NO_MODIFIER), Some(Action::Draw));
+        assert_eq!(bindings.lookup(left, SHIFT), Some(Action::Draw));
+        assert_eq!(bindings.lookup(left, CTRL), None);
+        assert_eq!(bindings.lookup(key(Key::LShift), SHIFT), Some(Action::Constrain));
+        assert_eq!(bindings.lookup(key(Key::RShift), SHIFT), Some(Action::Constrain));
+        assert_eq!(bindings.lookup(key(Key::LShift), SHIFT | CTRL), None);
+    }
+
+    #[test]
+    fn parse() {
+        let bindings = Bindings::parse("# comment\n\
+                                        move = MouseMiddle, ctrl+MouseLeft\n\
+                                        random-init =\n\
+                                        zoom-in = Equals, Ctrl+Up  # trailing comment\n")
+            .unwrap();
+        let left = Button::Mouse(MouseButton::Left);
+        assert_eq!(bindings.lookup(left, CTRL), Some(Action::Move));
+        assert_eq!(bindings.lookup(left, NO_MODIFIER), Some(Action::Draw));
+        assert_eq!(bindings.lookup(key(Key::R), NO_MODIFIER), None);
+        assert_eq!(bindings.lookup(key(Key::Up), CTRL), Some(Action::ZoomIn));
+        assert_eq!(bindings.lookup(key(Key::Equals), NO_MODIFIER), Some(Action::ZoomIn));
+        assert_eq!(bindings.lookup(key(Key::C), NO_MODIFIER), Some(Action::Clear));
+
+        // Listing an action replaces all of its default triggers.
+        let bindings = Bindings::parse("clear = K").unwrap();
+        assert_eq!(bindings.lookup(key(Key::K), NO_MODIFIER), Some(Action::Clear));
+        assert_eq!(bindings.lookup(key(Key::C), NO_MODIFIER), None);
+    }
+
+    #[test]
+    fn reject_malformed_bindings() {
+        for (content, error) in vec![("clear", "line 1: expected `action = triggers`"),
+                                     ("\nexplode = K", "line 2: unknown action `explode`"),
+                                     ("clear = Kay", "line 1: unknown key or button `Kay`"),
+                                     ("clear = Hyper+K", "line 1: unknown modifier `Hyper`")] {
+            assert_eq!(Bindings::parse(content).err(), Some(error.to_string()));
+        }
+    }
+}
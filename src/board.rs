@@ -1,11 +1,16 @@
-use geom::{Point, Size};
+use geom::{Move, Point, Size};
+use grid::EdgeMode;
 use rand::Rng;
-use std::mem;
+use rule::{Neighbourhood, Rule};
+use std::{cmp, mem};
 
 type Cell = u64;
 const BITS: usize = 64;
 const MSB: Cell = 1 << (BITS - 1);
 
+// Number of cells added to a side of an auto-expanding board at once.
+const EXPAND_STEP: i32 = 64;
+
 #[derive(Debug, Clone)]
 pub struct Board {
     size: Size,
@@ -14,26 +19,273 @@ pub struct Board {
     ls: Vec<Cell>,
     rs: Vec<Cell>,
     buffer: Vec<Cell>,
+    rule: Rule,
+    // Bit planes holding the age of dying cells in Generations rules, least significant first.
+    // A cell in state `n >= 2` has age `n - 1`.
+    planes: Vec<Vec<Cell>>,
+    edge: EdgeMode,
+    // World coordinates of the top-left cell, which change as the board expands.
+    origin: Point,
+    max_size: Option<Size>,
 }
 
 impl Board {
-    pub fn new_empty(size: Size) -> Self {
+    /// Creates an empty board. A bounded grid in the rule overrides `size`.
+    pub fn with_rule(size: Size, rule: Rule) -> Self {
+        let size = rule.grid().map_or(size, |grid| grid.size());
         let hsize = (size.0 + ((BITS - 1) as i32)) / (BITS as i32) + 1;
         let len = (hsize as usize) * (((size.1 + 2) as usize)) + 1;
-        Board {
+        let mut board = Board {
             hsize: hsize,
             size: size,
             table: vec![0; len],
             ls: vec![0; len],
             rs: vec![0; len],
             buffer: vec![0; len],
-        }
+            rule: Rule::default(),
+            planes: vec![],
+            edge: EdgeMode::default(),
+            origin: Point(0, 0),
+            max_size: None,
+        };
+        board.set_rule(rule);
+        board
     }
 
     pub fn size(&self) -> Size {
         self.size
     }
 
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// Changes the rule. Dying cells are cleared if the number of states changes.
+    ///
+    /// The size of a bounded grid in the rule must match the board.
+    pub fn set_rule(&mut self, rule: Rule) {
+        debug_assert!(rule.grid().map_or(true, |grid| grid.size() == self.size));
+        let num_planes = if rule.states() > 2 {
+            (32 - (rule.states() - 1).leading_zeros()) as usize
+        } else {
+            0
+        };
+        if rule.states() != self.rule.states() || num_planes != self.planes.len() {
+            self.planes = vec![vec![0; self.table.len()]; num_planes];
+        }
+        self.rule = rule;
+    }
+
+    pub fn edge(&self) -> EdgeMode {
+        self.edge
+    }
+
+    pub fn set_edge(&mut self, edge: EdgeMode) {
+        self.edge = edge;
+    }
+
+    /// Returns the world coordinates of the top-left cell.
+    pub fn origin(&self) -> Point {
+        self.origin
+    }
+
+    pub fn set_origin(&mut self, origin: Point) {
+        self.origin = origin;
+    }
+
+    pub fn max_size(&self) -> Option<Size> {
+        self.max_size
+    }
+
+    /// Makes the board expand up to `max_size` whenever cells come close to an edge, or turns
+    /// expansion off with `None`. Boards with a bounded grid never expand.
+    pub fn set_max_size(&mut self, max_size: Option<Size>) {
+        self.max_size = max_size;
+    }
+
+    /// Changes the size of the board, moving each cell by `shift`. Cells moved out of the
+    /// board are dropped, and the world coordinates of the cells that stay are kept.
+    pub fn resize(&mut self, size: Size, shift: Move) {
+        self.transform(size, |old, src, new, dst| for y in 0..size.1 {
+            for cx in 0..(new.hsize - 1) {
+                let x = cx * (BITS as i32) - shift.0;
+                dst[new.offset(cx, y)] = old.row_bits(src, x, y - shift.1) & new.word_mask(cx);
+            }
+        });
+        self.origin = Point(self.origin.0 - shift.0, self.origin.1 - shift.1);
+    }
+
+    /// Changes the size of the board, keeping the cells at `anchor` in place.
+    pub fn resize_anchored(&mut self, size: Size, anchor: Anchor) {
+        let shift = anchor.shift(self.size, size);
+        self.resize(size, shift);
+    }
+
+    /// Moves the cells by `by` within the board, dropping those moved out.
+    pub fn translate(&mut self, by: Move) {
+        let size = self.size;
+        self.resize(size, by);
+        self.origin = Point(self.origin.0 + by.0, self.origin.1 + by.1);
+    }
+
+    /// Shrinks the board to the smallest rectangle holding all non-dead cells. An empty board
+    /// is left as it is.
+    pub fn crop(&mut self) {
+        if let Some((p, size)) = self.bounding_box() {
+            self.resize(size, Move(-p.0, -p.1));
+        }
+    }
+
+    /// Returns the top-left corner and the size of the smallest rectangle holding all non-dead
+    /// cells, or `None` if there are none.
+    pub fn bounding_box(&self) -> Option<(Point, Size)> {
+        let mut min = Point(self.size.0, self.size.1);
+        let mut max = Point(-1, -1);
+        for y in 0..self.size.1 {
+            for cx in 0..(self.hsize - 1) {
+                let o = self.offset(cx, y);
+                let word = self.planes.iter().fold(self.table[o], |acc, p| acc | p[o]) &
+                           self.word_mask(cx);
+                if word == 0 {
+                    continue;
+                }
+                let x = cx * (BITS as i32);
+                min = Point(cmp::min(min.0, x + word.leading_zeros() as i32),
+                            cmp::min(min.1, y));
+                max = Point(cmp::max(max.0, x + (BITS as i32) - 1 - word.trailing_zeros() as i32),
+                            y);
+            }
+        }
+        if max.1 < 0 {
+            None
+        } else {
+            Some((min, Size(max.0 - min.0 + 1, max.1 - min.1 + 1)))
+        }
+    }
+
+    /// Combines the live cells of `other`, with its top-left cell at `offset`, into this board.
+    /// Cells not covered by the other board are combined with dead cells, and dying cells of
+    /// Generations rules are cleared.
+    pub fn combine(&mut self, other: &Board, offset: Point, op: BoolOp) {
+        let mut table = vec![0; self.table.len()];
+        self.for_each_combined(other, offset, op, |o, word| table[o] = word);
+        self.table = table;
+        for plane in &mut self.planes {
+            for v in plane.iter_mut() {
+                *v = 0;
+            }
+        }
+    }
+
+    /// Returns the number of live cells `combine` would leave.
+    pub fn count_combined(&self, other: &Board, offset: Point, op: BoolOp) -> u32 {
+        let mut count = 0;
+        self.for_each_combined(other, offset, op, |_, word| count += word.count_ones());
+        count
+    }
+
+    /// Mirrors the board left to right.
+    pub fn flip_horizontal(&mut self) {
+        let size = self.size;
+        self.transform(size, |old, src, new, dst| for y in 0..size.1 {
+            for cx in 0..(new.hsize - 1) {
+                let x = size.0 - (cx + 1) * (BITS as i32);
+                dst[new.offset(cx, y)] = reverse_bits(old.row_bits(src, x, y)) &
+                                         new.word_mask(cx);
+            }
+        });
+    }
+
+    /// Mirrors the board top to bottom.
+    pub fn flip_vertical(&mut self) {
+        let size = self.size;
+        self.transform(size, |old, src, new, dst| for y in 0..size.1 {
+            for cx in 0..(new.hsize - 1) {
+                dst[new.offset(cx, y)] = src[old.offset(cx, size.1 - 1 - y)] & new.word_mask(cx);
+            }
+        });
+    }
+
+    /// Rotates the board a quarter turn clockwise, swapping its width and height.
+    pub fn rotate_clockwise(&mut self) {
+        self.transpose();
+        self.flip_horizontal();
+    }
+
+    /// Rotates the board a quarter turn counterclockwise, swapping its width and height.
+    pub fn rotate_counterclockwise(&mut self) {
+        self.transpose();
+        self.flip_vertical();
+    }
+
+    pub fn rotate_180(&mut self) {
+        self.flip_horizontal();
+        self.flip_vertical();
+    }
+
+    /// Mirrors the board across its main diagonal, 64x64 cells at a time.
+    fn transpose(&mut self) {
+        let size = Size(self.size.1, self.size.0);
+        self.transform(size, |old, src, new, dst| {
+            let mut block = [0; BITS];
+            for bx in 0..(old.hsize - 1) {
+                for by in 0..(new.hsize - 1) {
+                    for (i, row) in block.iter_mut().enumerate() {
+                        let y = by * (BITS as i32) + i as i32;
+                        *row = old.row_bits(src, bx * (BITS as i32), y);
+                    }
+                    transpose_block(&mut block);
+                    for (i, &row) in block.iter().enumerate() {
+                        let y = bx * (BITS as i32) + i as i32;
+                        if y < size.1 {
+                            dst[new.offset(by, y)] = row & new.word_mask(by);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Calls `f(offset, word)` with each word of live cells combined with those of `other`.
+    fn for_each_combined<F>(&self, other: &Board, offset: Point, op: BoolOp, mut f: F)
+        where F: FnMut(usize, Cell)
+    {
+        for y in 0..self.size.1 {
+            for cx in 0..(self.hsize - 1) {
+                let o = self.offset(cx, y);
+                let x = cx * (BITS as i32) - offset.0;
+                let theirs = other.row_bits(&other.table, x, y - offset.1);
+                f(o, op.apply(self.table[o], theirs) & self.word_mask(cx));
+            }
+        }
+    }
+
+    /// Replaces the board with an empty one of `size` and the same settings, filling each layer
+    /// of cells (`table` and the age planes) with `f(old, src, new, dst)`.
+    fn transform<F>(&mut self, size: Size, mut f: F)
+        where F: FnMut(&Board, &[Cell], &Board, &mut [Cell])
+    {
+        debug_assert!(self.rule.grid().map_or(true, |grid| grid.size() == size));
+        let mut board = Board::with_rule(size, self.rule.clone());
+        board.edge = self.edge;
+        board.origin = self.origin;
+        board.max_size = self.max_size;
+
+        let mut table = mem::replace(&mut board.table, vec![]);
+        f(self, &self.table, &board, &mut table);
+        board.table = table;
+        for (i, src) in self.planes.iter().enumerate() {
+            let mut plane = mem::replace(&mut board.planes[i], vec![]);
+            f(self, src, &board, &mut plane);
+            board.planes[i] = plane;
+        }
+        *self = board;
+    }
+
+    pub fn population(&self) -> u32 {
+        self.count_in_rect(Point(0, 0), self.size)
+    }
+
     pub fn contains(&self, p: Point) -> bool {
         0 <= p.0 && p.0 < self.size.0 && 0 <= p.1 && p.1 < self.size.1
     }
@@ -44,23 +296,80 @@ impl Board {
     }
 
     pub fn set(&mut self, p: Point, v: bool) {
+        self.set_state(p, if v { 1 } else { 0 });
+    }
+
+    /// Returns the state of the cell: 0 for dead, 1 for alive and 2 or more for dying cells.
+    pub fn state(&self, p: Point) -> u8 {
+        if self.get(p) {
+            return 1;
+        }
         let (offset, mask) = self.get_pos(p);
-        if v {
+        let age = self.planes
+            .iter()
+            .enumerate()
+            .filter(|&(_, plane)| (plane[offset] & mask) != 0)
+            .fold(0, |acc, (i, _)| acc | (1 << i));
+        if age == 0 { 0 } else { (age + 1) as u8 }
+    }
+
+    /// Sets the state of the cell. States beyond the rule's last state are treated as dead.
+    pub fn set_state(&mut self, p: Point, state: u8) {
+        let (offset, mask) = self.get_pos(p);
+        let state = if (state as u32) < self.rule.states() { state } else { 0 };
+        if state == 1 {
             self.table[offset] |= mask;
         } else {
             self.table[offset] &= !mask;
         }
+        let age = if state >= 2 { state - 1 } else { 0 };
+        for (i, plane) in self.planes.iter_mut().enumerate() {
+            if (age >> i) & 1 != 0 {
+                plane[offset] |= mask;
+            } else {
+                plane[offset] &= !mask;
+            }
+        }
+    }
+
+    pub fn count_in_rect(&self, p: Point, size: Size) -> u32 {
+        let x0 = cmp::max(p.0, 0);
+        let y0 = cmp::max(p.1, 0);
+        let x1 = cmp::min(p.0 + size.0, self.size.0);
+        let y1 = cmp::min(p.1 + size.1, self.size.1);
+
+        let mut count = 0;
+        for y in y0..y1 {
+            let mut x = x0;
+            while x < x1 {
+                let (offset, _) = self.get_pos(Point(x, y));
+                let bit = (x % (BITS as i32)) as usize;
+                let len = cmp::min(BITS - bit, (x1 - x) as usize);
+                let upper = !0 >> bit;
+                let lower = if bit + len < BITS { !0 >> (bit + len) } else { 0 };
+                let word = self.planes.iter().fold(self.table[offset], |acc, p| acc | p[offset]);
+                count += (word & upper & !lower).count_ones();
+                x += len as i32;
+            }
+        }
+        count
     }
 
     pub fn clear(&mut self) {
         for v in &mut self.table {
             *v = 0;
         }
+        for plane in &mut self.planes {
+            for v in plane {
+                *v = 0;
+            }
+        }
     }
 
     pub fn random_init<R>(&mut self, rng: &mut R)
         where R: Rng
     {
+        self.clear();
         for x in 0..self.size.0 {
             for y in 0..self.size.1 {
                 self.set(Point(x, y), rng.gen());
@@ -69,8 +378,15 @@ impl Board {
     }
 
     pub fn grow(&mut self) {
-        for cx in 0..self.hsize {
-            for cy in 0..self.size.1 {
+        self.expand_to_fit();
+        if !self.rule.is_life_like() {
+            self.grow_range();
+            return;
+        }
+
+        self.fill_ghosts();
+        for cx in 0..(self.hsize - 1) {
+            for cy in -1..(self.size.1 + 1) {
                 let o = self.offset(cx, cy);
                 let tc = self.table[o];
                 let tl = self.table[self.offset(cx - 1, cy)];
@@ -80,20 +396,72 @@ impl Board {
             }
         }
 
-        for cx in 0..self.hsize {
+        let conway = self.rule.is_conway();
+        let neighbourhood = self.rule.neighbourhood();
+        let birth = self.rule.birth_mask();
+        let survival = self.rule.survival_mask();
+
+        for cx in 0..(self.hsize - 1) {
             for cy in 0..self.size.1 {
                 let oc = self.offset(cx, cy);
                 let ou = self.offset(cx, cy - 1);
                 let od = self.offset(cx, cy + 1);
 
-                let t = [self.ls[ou],
+                let t = match neighbourhood {
+                    Neighbourhood::Moore => {
+                        [self.ls[ou],
                          self.ls[oc],
                          self.ls[od],
                          self.table[ou],
                          self.table[od],
                          self.rs[ou],
                          self.rs[oc],
-                         self.rs[od]];
+                         self.rs[od]]
+                    }
+                    Neighbourhood::VonNeumann => {
+                        [self.ls[oc], self.rs[oc], self.table[ou], self.table[od], 0, 0, 0, 0]
+                    }
+                    Neighbourhood::Hexagonal if cy % 2 == 0 => {
+                        [self.ls[oc],
+                         self.rs[oc],
+                         self.ls[ou],
+                         self.table[ou],
+                         self.ls[od],
+                         self.table[od],
+                         0,
+                         0]
+                    }
+                    Neighbourhood::Hexagonal => {
+                        [self.ls[oc],
+                         self.rs[oc],
+                         self.table[ou],
+                         self.rs[ou],
+                         self.table[od],
+                         self.rs[od],
+                         0,
+                         0]
+                    }
+                };
+
+                if !conway {
+                    let alive = self.table[oc];
+                    let dying = self.planes.iter().fold(0, |acc, p| acc | p[oc]);
+                    let (born, survive) = match self.rule.table() {
+                        Some(table) => {
+                            (lookup_configs(table, &t, 0), lookup_configs(table, &t, !0))
+                        }
+                        None => {
+                            let counts = count_neighbours(&t);
+                            (select_counts(&counts, birth), select_counts(&counts, survival))
+                        }
+                    };
+                    let next = (born & !alive & !dying) | (survive & alive);
+                    if !self.planes.is_empty() {
+                        self.age(oc, alive & !next);
+                    }
+                    self.buffer[oc] = next;
+                    continue;
+                }
 
                 let mut c0 = !(t[0] | t[1]);
                 let mut c1 = t[0] ^ t[1];
@@ -151,6 +519,282 @@ impl Board {
         mem::swap(&mut self.table, &mut self.buffer);
     }
 
+    /// Computes the next generation of a Larger than Life rule.
+    ///
+    /// The live cells in a neighbourhood are counted in constant time with a summed-area table
+    /// covering the board and a margin of `range` cells around it. For the von Neumann
+    /// neighbourhood the table is rotated 45 degrees, which turns each diamond into a square.
+    fn grow_range(&mut self) {
+        let Size(w, h) = self.size;
+        let (w, h) = (w as usize, h as usize);
+        let r = self.rule.range() as usize;
+        let (ew, eh) = (w + 2 * r, h + 2 * r);
+        let stride = ew + 1;
+
+        // `rows[y * stride + x]` is the number of live cells left of `x` in row `y`, with the
+        // board starting at `(r, r)`.
+        let mut rows = vec![0u32; stride * eh];
+        for y in 0..eh {
+            for x in 0..ew {
+                let p = Point(x as i32 - r as i32, y as i32 - r as i32);
+                let alive = if self.contains(p) {
+                    self.get(p)
+                } else {
+                    self.ghost(p)
+                };
+                rows[y * stride + x + 1] = rows[y * stride + x] + alive as u32;
+            }
+        }
+
+        let mut counts = vec![0u32; w * h];
+        match self.rule.neighbourhood() {
+            Neighbourhood::Moore => {
+                // `table[y * stride + x]` is the number of live cells above and left of (x, y).
+                let mut table = vec![0u32; stride * (eh + 1)];
+                for y in 0..eh {
+                    for x in 0..stride {
+                        table[(y + 1) * stride + x] = table[y * stride + x] + rows[y * stride + x];
+                    }
+                }
+                for y in 0..h {
+                    let (y0, y1) = (y, y + 2 * r + 1);
+                    for x in 0..w {
+                        let (x0, x1) = (x, x + 2 * r + 1);
+                        counts[y * w + x] = table[y1 * stride + x1] + table[y0 * stride + x0] -
+                                            table[y0 * stride + x1] -
+                                            table[y1 * stride + x0];
+                    }
+                }
+            }
+            Neighbourhood::VonNeumann => {
+                // Turned 45 degrees, with `(x, y)` at `(x + y, x - y + eh - 1)`, each diamond is
+                // a square. `table[u * side + v]` is the number of live cells at rotated
+                // coordinates below `u` and `v`.
+                let side = ew + eh;
+                let mut table = vec![0u32; side * side];
+                for y in 0..eh {
+                    for x in 0..ew {
+                        let (u, v) = (x + y, x + eh - 1 - y);
+                        table[(u + 1) * side + v + 1] = rows[y * stride + x + 1] -
+                                                        rows[y * stride + x];
+                    }
+                }
+                for u in 1..side {
+                    for v in 1..side {
+                        table[u * side + v] += table[(u - 1) * side + v] +
+                                               table[u * side + v - 1] -
+                                               table[(u - 1) * side + v - 1];
+                    }
+                }
+                for y in 0..h {
+                    for x in 0..w {
+                        let (u0, u1) = (x + y + r, x + y + 3 * r + 1);
+                        let (v0, v1) = (x + eh - 1 - y - r, x + eh - y + r);
+                        counts[y * w + x] = table[u1 * side + v1] + table[u0 * side + v0] -
+                                            table[u0 * side + v1] -
+                                            table[u1 * side + v0];
+                    }
+                }
+            }
+            Neighbourhood::Hexagonal => unreachable!("hexagonal rules have range 1"),
+        }
+
+        let middle = self.rule.middle();
+        let states = self.rule.states();
+        let mut next = vec![0u8; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let p = Point(x as i32, y as i32);
+                let state = self.state(p);
+                let count = counts[y * w + x];
+                next[y * w + x] = match state {
+                    0 if self.rule.is_birth(count) => 1,
+                    0 => 0,
+                    1 => {
+                        let count = if middle { count } else { count - 1 };
+                        if self.rule.is_survival(count) {
+                            1
+                        } else if states > 2 {
+                            2
+                        } else {
+                            0
+                        }
+                    }
+                    s if (s as u32) + 1 < states => s + 1,
+                    _ => 0,
+                };
+            }
+        }
+
+        self.clear();
+        for y in 0..h {
+            for x in 0..w {
+                if next[y * w + x] != 0 {
+                    self.set_state(Point(x as i32, y as i32), next[y * w + x]);
+                }
+            }
+        }
+    }
+
+    /// Advances the dying cells in the word at `o` by one state and starts `dying` cells.
+    fn age(&mut self, o: usize, dying: Cell) {
+        let mut carry = self.planes.iter().fold(0, |acc, p| acc | p[o]);
+        for plane in &mut self.planes {
+            let v = plane[o];
+            plane[o] = v ^ carry;
+            carry &= v;
+        }
+
+        let last = self.rule.states() - 1;
+        let mut expired = !0;
+        for (i, plane) in self.planes.iter().enumerate() {
+            expired &= if (last >> i) & 1 != 0 { plane[o] } else { !plane[o] };
+        }
+
+        for (i, plane) in self.planes.iter_mut().enumerate() {
+            plane[o] &= !expired;
+            if i == 0 {
+                plane[o] |= dying;
+            } else {
+                plane[o] &= !dying;
+            }
+        }
+    }
+
+    /// Sets the cells just outside the board, which `grow` reads as neighbours, and clears the
+    /// unused bits of the last word in each row.
+    fn fill_ghosts(&mut self) {
+        let Size(w, h) = self.size;
+        if w == 0 || h == 0 {
+            return;
+        }
+
+        let last = (w - 1) / (BITS as i32);
+        let unused = !self.word_mask(last);
+        for cx in -1..self.hsize {
+            let (top, bottom) = (self.offset(cx, -1), self.offset(cx, h));
+            self.table[top] = 0;
+            self.table[bottom] = 0;
+        }
+        for y in 0..h {
+            let (left, o) = (self.offset(-1, y), self.offset(last, y));
+            self.table[left] = 0;
+            self.table[o] &= !unused;
+        }
+
+        let mut ghosts = vec![];
+        for y in -1..(h + 1) {
+            ghosts.push(Point(-1, y));
+            ghosts.push(Point(w, y));
+        }
+        for x in 0..w {
+            ghosts.push(Point(x, -1));
+            ghosts.push(Point(x, h));
+        }
+        for p in ghosts {
+            if self.ghost(p) {
+                let (offset, mask) = self.get_pos(p);
+                self.table[offset] |= mask;
+            }
+        }
+    }
+
+    /// Returns whether a cell outside of the board counts as alive.
+    fn ghost(&self, p: Point) -> bool {
+        if let Some(p) = self.rule.grid().and_then(|grid| grid.map(p)) {
+            return self.get(p);
+        }
+        match self.edge {
+            EdgeMode::Absorbing => false,
+            EdgeMode::Reflecting => {
+                let mirror = |n: i32, len: i32| if n < 0 {
+                    -n - 1
+                } else if n >= len {
+                    2 * len - 1 - n
+                } else {
+                    n
+                };
+                let p = Point(mirror(p.0, self.size.0), mirror(p.1, self.size.1));
+                self.contains(p) && self.get(p)
+            }
+            EdgeMode::Alive => true,
+            EdgeMode::Noisy(density) => noise() < density,
+        }
+    }
+
+    /// Adds space on the sides where cells are within reach of the edge, unless the board has
+    /// reached its maximum size. Rows are added above in pairs to keep hexagonal rows aligned.
+    fn expand_to_fit(&mut self) {
+        let max = match self.max_size {
+            Some(max) if self.rule.grid().is_none() => max,
+            _ => return,
+        };
+        let Size(w, h) = self.size;
+        let m = self.rule.range() as i32 + 1;
+        let near = |p: Point, size: Size| self.count_in_rect(p, size) > 0;
+        let room = Size(cmp::max(max.0 - w, 0), cmp::max(max.1 - h, 0));
+
+        let left = if near(Point(0, 0), Size(m, h)) {
+            cmp::min(EXPAND_STEP, room.0)
+        } else {
+            0
+        };
+        let right = if near(Point(w - m, 0), Size(m, h)) {
+            cmp::min(EXPAND_STEP, room.0 - left)
+        } else {
+            0
+        };
+        let top = if near(Point(0, 0), Size(w, m)) {
+            cmp::min(EXPAND_STEP, room.1) / 2 * 2
+        } else {
+            0
+        };
+        let bottom = if near(Point(0, h - m), Size(w, m)) {
+            cmp::min(EXPAND_STEP, room.1 - top)
+        } else {
+            0
+        };
+
+        if left + right + top + bottom > 0 {
+            self.resize(Size(w + left + right, h + top + bottom), Move(left, top));
+        }
+    }
+
+    /// Returns the cells from `x` to `x + BITS` in row `y` of `words`, which is laid out like
+    /// `table`. Cells outside of the board are dead.
+    fn row_bits(&self, words: &[Cell], x: i32, y: i32) -> Cell {
+        if y < 0 || y >= self.size.1 {
+            return 0;
+        }
+        let last = (self.size.0 - 1) / (BITS as i32);
+        let word = |cx: i32| if 0 <= cx && cx <= last {
+            words[self.offset(cx, y)] & self.word_mask(cx)
+        } else {
+            0
+        };
+        let cx = if x >= 0 {
+            x / (BITS as i32)
+        } else {
+            (x - (BITS as i32) + 1) / (BITS as i32)
+        };
+        match x - cx * (BITS as i32) {
+            0 => word(cx),
+            bit => (word(cx) << bit) | (word(cx + 1) >> ((BITS as i32) - bit)),
+        }
+    }
+
+    /// Returns the bits of the word at `cx` that lie on the board.
+    fn word_mask(&self, cx: i32) -> Cell {
+        let end = self.size.0 - cx * (BITS as i32);
+        if end >= BITS as i32 {
+            !0
+        } else if end <= 0 {
+            0
+        } else {
+            !(!0 >> end)
+        }
+    }
+
     fn offset(&self, cx: i32, cy: i32) -> usize {
         ((cx + 1) as usize) + ((cy + 1) as usize) * (self.hsize as usize)
     }
@@ -159,7 +803,444 @@ impl Board {
         let cx = (p.0 + (BITS as i32)) / (BITS as i32) - 1;
         let cy = p.1;
         let offset = self.offset(cx, cy);
-        let mask = MSB >> (p.0 % (BITS as i32));
+        let mask = MSB >> ((p.0 + (BITS as i32)) % (BITS as i32));
         (offset, mask)
     }
 }
+
+/// Where the cells stay in place when a board is resized.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Returns the anchor of the ninth of a board of `size` that holds `p`.
+    pub fn at(p: Point, size: Size) -> Anchor {
+        let third = |v: i32, len: i32| cmp::min(cmp::max(v * 3 / cmp::max(len, 1), 0), 2);
+        match (third(p.0, size.0), third(p.1, size.1)) {
+            (0, 0) => Anchor::TopLeft,
+            (1, 0) => Anchor::Top,
+            (2, 0) => Anchor::TopRight,
+            (0, 1) => Anchor::Left,
+            (1, 1) => Anchor::Center,
+            (2, 1) => Anchor::Right,
+            (0, _) => Anchor::BottomLeft,
+            (1, _) => Anchor::Bottom,
+            (_, _) => Anchor::BottomRight,
+        }
+    }
+
+    /// Returns how far the cells move when a board of `old` size is resized to `new`.
+    pub fn shift(&self, old: Size, new: Size) -> Move {
+        let (h, v) = match *self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        };
+        Move((new.0 - old.0) * h / 2, (new.1 - old.1) * v / 2)
+    }
+}
+
+/// A boolean operation on the cells of two boards.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BoolOp {
+    And,
+    Or,
+    Xor,
+    /// Cells alive on the first board but not on the second.
+    Difference,
+}
+
+impl BoolOp {
+    pub fn apply(&self, a: Cell, b: Cell) -> Cell {
+        match *self {
+            BoolOp::And => a & b,
+            BoolOp::Or => a | b,
+            BoolOp::Xor => a ^ b,
+            BoolOp::Difference => a & !b,
+        }
+    }
+}
+
+/// Returns a random number in `[0, 1)` for noisy edges.
+#[cfg(not(target_arch = "wasm32"))]
+fn noise() -> f64 {
+    ::rand::random()
+}
+
+/// The WebAssembly build has no thread RNG, so noisy edges draw from a fixed sequence there.
+#[cfg(target_arch = "wasm32")]
+fn noise() -> f64 {
+    use rand::XorShiftRng;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static NOISE: RefCell<XorShiftRng> = RefCell::new(XorShiftRng::new_unseeded());
+    }
+    NOISE.with(|rng| rng.borrow_mut().gen())
+}
+
+/// Reverses the order of the cells in a word.
+fn reverse_bits(mut v: Cell) -> Cell {
+    v = ((v >> 1) & 0x5555555555555555) | ((v & 0x5555555555555555) << 1);
+    v = ((v >> 2) & 0x3333333333333333) | ((v & 0x3333333333333333) << 2);
+    v = ((v >> 4) & 0x0f0f0f0f0f0f0f0f) | ((v & 0x0f0f0f0f0f0f0f0f) << 4);
+    v.swap_bytes()
+}
+
+/// Transposes a 64x64 block of cells in place, so that row `i` holds what was column `i`.
+fn transpose_block(rows: &mut [Cell; BITS]) {
+    let mut j = BITS / 2;
+    let mut m: Cell = 0x00000000ffffffff;
+    while j != 0 {
+        let mut k = 0;
+        while k < BITS {
+            let t = (rows[k] ^ (rows[k + j] >> j)) & m;
+            rows[k] ^= t;
+            rows[k + j] ^= t << j;
+            k = (k + j + 1) & !j;
+        }
+        j /= 2;
+        m ^= m << j;
+    }
+}
+
+/// Adds up eight neighbour words bitwise, returning the 4-bit count of each cell as bit planes.
+fn count_neighbours(t: &[Cell; 8]) -> [Cell; 4] {
+    let (s0, c0) = full_add(t[0], t[1], t[2]);
+    let (s1, c1) = full_add(t[3], t[4], t[5]);
+    let (s2, c2) = (t[6] ^ t[7], t[6] & t[7]);
+    let (b0, k) = full_add(s0, s1, s2);
+    let (x, y) = full_add(c0, c1, c2);
+    let (b1, z) = (x ^ k, x & k);
+    [b0, b1, y ^ z, y & z]
+}
+
+fn full_add(a: Cell, b: Cell, c: Cell) -> (Cell, Cell) {
+    (a ^ b ^ c, (a & b) | (c & (a ^ b)))
+}
+
+/// Returns the cells whose neighbour count is in the `mask` bit set.
+fn select_counts(counts: &[Cell; 4], mask: u16) -> Cell {
+    let mut result = 0;
+    for n in (0..9).filter(|n| (mask & (1 << n)) != 0) {
+        let mut eq = !0;
+        for (i, &c) in counts.iter().enumerate() {
+            eq &= if (n >> i) & 1 != 0 { c } else { !c };
+        }
+        result |= eq;
+    }
+    result
+}
+
+/// Looks up the next state of each cell in the 3x3 configuration table of a non-totalistic rule.
+fn lookup_configs(table: &[bool], t: &[Cell; 8], centre: Cell) -> Cell {
+    // Bits of NW, W, SW, N, S, NE, E and SE in the table index.
+    const CONFIG_BITS: [u32; 8] = [0, 3, 6, 1, 7, 2, 5, 8];
+
+    let mut next = 0;
+    for k in 0..BITS {
+        let index = t.iter()
+            .zip(&CONFIG_BITS)
+            .fold(((centre >> k) & 1) << 4, |acc, (w, &b)| acc | (((w >> k) & 1) << b));
+        if table[index as usize] {
+            next |= 1 << k;
+        }
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    fn board(rule: &str, size: Size, cells: &[(i32, i32)]) -> Board {
+        let mut board = Board::with_rule(size, rule.parse().unwrap());
+        for &(x, y) in cells {
+            board.set(Point(x, y), true);
+        }
+        board
+    }
+
+    fn live_cells(board: &Board) -> Vec<(i32, i32)> {
+        let Size(w, h) = board.size();
+        let mut cells = vec![];
+        for y in 0..h {
+            for x in 0..w {
+                if board.get(Point(x, y)) {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Asserts that each cell of `after` holds the cell of `before` at `source(x, y)`, or is dead
+    /// if that lies outside of `before`.
+    fn assert_moved<F>(before: &Board, after: &Board, size: Size, source: F)
+        where F: Fn(i32, i32) -> Point
+    {
+        assert_eq!(after.size(), size);
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let p = source(x, y);
+                let expected = before.contains(p) && before.get(p);
+                assert_eq!(after.get(Point(x, y)), expected, "({}, {})", x, y);
+            }
+        }
+    }
+
+    /// Computes the next generation of a two-state range rule on an absorbing board cell by cell.
+    fn naive_next(board: &Board,
+                  r: i32,
+                  diamond: bool,
+                  birth: (u32, u32),
+                  survival: (u32, u32))
+                  -> Vec<(i32, i32)> {
+        let Size(w, h) = board.size();
+        let mut cells = vec![];
+        for y in 0..h {
+            for x in 0..w {
+                let mut count = 0;
+                for dy in -r..(r + 1) {
+                    for dx in -r..(r + 1) {
+                        let p = Point(x + dx, y + dy);
+                        let inside = !diamond || dx.abs() + dy.abs() <= r;
+                        if (dx, dy) != (0, 0) && inside && board.contains(p) && board.get(p) {
+                            count += 1;
+                        }
+                    }
+                }
+                let range = if board.get(Point(x, y)) { survival } else { birth };
+                if range.0 <= count && count <= range.1 {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn blinker() {
+        let mut board = board("B3/S23", Size(5, 5), &[(1, 2), (2, 2), (3, 2)]);
+        board.grow();
+        assert_eq!(live_cells(&board), vec![(2, 1), (2, 2), (2, 3)]);
+        board.grow();
+        assert_eq!(live_cells(&board), vec![(1, 2), (2, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn glider() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut board = board("B3/S23", Size(10, 10), &glider);
+        for _ in 0..4 {
+            board.grow();
+        }
+        let moved = glider.iter().map(|&(x, y)| (x + 1, y + 1)).collect::<Vec<_>>();
+        assert_eq!(live_cells(&board), moved);
+    }
+
+    #[test]
+    fn glider_on_torus() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut board = board("B3/S23:T20,20", Size(5, 5), &glider);
+        assert_eq!(board.size(), Size(20, 20));
+        for _ in 0..40 {
+            board.grow();
+        }
+        assert_ne!(live_cells(&board), glider.to_vec());
+        for _ in 0..40 {
+            board.grow();
+        }
+        assert_eq!(live_cells(&board), glider.to_vec());
+    }
+
+    #[test]
+    fn glider_expands_board() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut expanding = board("B3/S23", Size(10, 10), &glider);
+        expanding.set_max_size(Some(Size(500, 500)));
+        for _ in 0..400 {
+            expanding.grow();
+        }
+        assert_eq!(expanding.population(), 5);
+        let Size(w, h) = expanding.size();
+        assert!(w > 100 && h > 100, "{:?}", expanding.size());
+
+        // The cells stay where they were in world coordinates while space is added on the left
+        // and top, so the glider has moved by 100 cells in each direction.
+        let origin = expanding.origin();
+        assert!(origin.0 < 0 && origin.1 < 0, "{:?}", origin);
+        let world = live_cells(&expanding)
+            .into_iter()
+            .map(|(x, y)| (x + origin.0, y + origin.1))
+            .collect::<Vec<_>>();
+        let moved = glider.iter().map(|&(x, y)| (x + 100, y + 100)).collect::<Vec<_>>();
+        assert_eq!(world, moved);
+    }
+
+    #[test]
+    fn transforms() {
+        for &size in &[Size(70, 130), Size(129, 5)] {
+            let Size(w, h) = size;
+            let mut before = board("B3/S23", size, &[]);
+            before.random_init(&mut XorShiftRng::from_seed([5, 6, 7, 8]));
+
+            let mut after = before.clone();
+            after.rotate_clockwise();
+            assert_moved(&before, &after, Size(h, w), |x, y| Point(y, h - 1 - x));
+
+            let mut after = before.clone();
+            after.rotate_counterclockwise();
+            assert_moved(&before, &after, Size(h, w), |x, y| Point(w - 1 - y, x));
+
+            let mut after = before.clone();
+            after.flip_horizontal();
+            assert_moved(&before, &after, size, |x, y| Point(w - 1 - x, y));
+
+            let mut after = before.clone();
+            after.translate(Move(5, -3));
+            assert_moved(&before, &after, size, |x, y| Point(x - 5, y + 3));
+            assert_eq!(after.origin(), Point(0, 0));
+
+            let mut after = before.clone();
+            after.resize_anchored(Size(w - 3, h + 5), Anchor::Center);
+            assert_moved(&before, &after, Size(w - 3, h + 5), |x, y| Point(x + 1, y - 2));
+            assert_eq!(after.origin(), Point(1, -2));
+        }
+
+        let mut sparse = board("B3/S23", Size(70, 130), &[(65, 7), (68, 120), (66, 3)]);
+        sparse.crop();
+        assert_eq!((sparse.size(), sparse.origin()), (Size(4, 118), Point(65, 3)));
+        assert_eq!(live_cells(&sparse), vec![(1, 0), (0, 4), (3, 117)]);
+    }
+
+    #[test]
+    fn combine() {
+        let mut rng = XorShiftRng::from_seed([9, 10, 11, 12]);
+        let mut mine = board("B3/S23", Size(100, 70), &[]);
+        mine.random_init(&mut rng);
+        let ops = [BoolOp::And, BoolOp::Or, BoolOp::Xor, BoolOp::Difference];
+        for &(size, offset) in &[(Size(129, 5), Point(-70, 2)), (Size(40, 60), Point(63, 1))] {
+            let mut theirs = board("B3/S23", size, &[]);
+            theirs.random_init(&mut rng);
+            for &op in &ops {
+                let mut combined = mine.clone();
+                combined.combine(&theirs, offset, op);
+                for y in 0..70 {
+                    for x in 0..100 {
+                        let a = mine.get(Point(x, y));
+                        let p = Point(x - offset.0, y - offset.1);
+                        let b = theirs.contains(p) && theirs.get(p);
+                        let expected = match op {
+                            BoolOp::And => a && b,
+                            BoolOp::Or => a || b,
+                            BoolOp::Xor => a != b,
+                            BoolOp::Difference => a && !b,
+                        };
+                        assert_eq!(combined.get(Point(x, y)), expected, "{:?} ({}, {})", op, x, y);
+                    }
+                }
+                assert_eq!(mine.count_combined(&theirs, offset, op), combined.population());
+            }
+        }
+    }
+
+    #[test]
+    fn brians_brain() {
+        let mut board = board("B2/S/C3", Size(6, 5), &[(2, 2), (3, 2)]);
+        board.grow();
+        assert_eq!(live_cells(&board), vec![(2, 1), (3, 1), (2, 3), (3, 3)]);
+        assert_eq!((board.state(Point(2, 2)), board.state(Point(3, 2))), (2, 2));
+        board.grow();
+        assert_eq!((board.state(Point(2, 2)), board.state(Point(3, 2))), (0, 0));
+        assert_eq!(board.state(Point(2, 1)), 2);
+    }
+
+    #[test]
+    fn hensel_configurations() {
+        // Each dead cell next to the pair sees two adjacent neighbours, which is `2a`.
+        let mut adjacent = board("B2a/S", Size(4, 3), &[(1, 1), (2, 1)]);
+        adjacent.grow();
+        assert_eq!(live_cells(&adjacent), vec![(1, 0), (2, 0), (1, 2), (2, 2)]);
+
+        let mut corners = board("B2c/S", Size(4, 3), &[(1, 1), (2, 1)]);
+        corners.grow();
+        assert_eq!(live_cells(&corners), vec![]);
+    }
+
+    #[test]
+    fn hexagonal() {
+        // Odd rows are shifted half a cell right, so (2, 3) touches (2, 2) and (3, 2) above it.
+        let mut square = board("B2/S34H", Size(6, 6), &[(2, 2), (3, 2), (2, 3), (3, 3)]);
+        square.grow();
+        assert_eq!(live_cells(&square),
+                   vec![(2, 1), (3, 2), (4, 2), (1, 3), (2, 3), (3, 4)]);
+    }
+
+    #[test]
+    fn von_neumann() {
+        let mut domino = board("B1/S1V", Size(6, 5), &[(2, 2), (3, 2)]);
+        domino.grow();
+        assert_eq!(live_cells(&domino),
+                   vec![(2, 1), (3, 1), (1, 2), (2, 2), (3, 2), (4, 2), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn edges() {
+        // The domino's mirror image above row 0 completes a block.
+        let mut domino = board("B3/S23", Size(6, 4), &[(2, 0), (3, 0)]);
+        domino.set_edge(EdgeMode::Reflecting);
+        domino.grow();
+        assert_eq!(live_cells(&domino), vec![(2, 0), (3, 0)]);
+
+        // Three live cells outside each edge cell give birth along the edges, except at the
+        // corners, which see five.
+        let framed = vec![(1, 0), (2, 0), (3, 0), (0, 1), (4, 1), (0, 2), (4, 2), (0, 3), (4, 3),
+                          (1, 4), (2, 4), (3, 4)];
+        let mut single = board("B3/S23", Size(5, 5), &[(2, 2)]);
+        single.set_edge(EdgeMode::Alive);
+        single.grow();
+        assert_eq!(live_cells(&single), framed);
+
+        let mut never = board("B3/S23", Size(5, 5), &[(2, 2)]);
+        never.set_edge(EdgeMode::Noisy(0.0));
+        never.grow();
+        assert_eq!(live_cells(&never), vec![]);
+
+        let mut always = board("B3/S23", Size(5, 5), &[(2, 2)]);
+        always.set_edge(EdgeMode::Noisy(1.0));
+        always.grow();
+        assert_eq!(live_cells(&always), framed);
+    }
+
+    #[test]
+    fn larger_than_life_counts() {
+        let cases = [("R3,C0,M0,S2..6,B3..5,NM", false), ("R3,C0,M0,S2..6,B3..5,NN", true)];
+        for &(rule, diamond) in &cases {
+            let mut board = board(rule, Size(29, 23), &[]);
+            board.random_init(&mut XorShiftRng::from_seed([1, 2, 3, 4]));
+            for _ in 0..3 {
+                let expected = naive_next(&board, 3, diamond, (3, 5), (2, 6));
+                board.grow();
+                assert_eq!(live_cells(&board), expected, "{}", rule);
+            }
+        }
+    }
+}
@@ -0,0 +1,601 @@
+use geom::{Point, Size};
+use rand::Rng;
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+pub const MAX_RADIUS: u32 = 50;
+
+// Stops of the colour map, evenly spaced from 0 to 1.
+const COLOR_MAP: [[u8; 3]; 5] = [[0, 0, 4], [87, 16, 110], [188, 55, 84], [249, 142, 9],
+                                 [252, 255, 164]];
+
+/// Shape of the kernel shells and of the growth mapping in Lenia.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Shape {
+    Exponential,
+    Polynomial,
+    Step,
+}
+
+impl Shape {
+    /// Returns the height of a kernel shell at `r` in `[0, 1)`.
+    fn kernel_core(&self, r: f64) -> f64 {
+        match *self {
+            Shape::Exponential if 0.0 < r && r < 1.0 => (4.0 - 1.0 / (r * (1.0 - r))).exp(),
+            Shape::Exponential => 0.0,
+            Shape::Polynomial => (4.0 * r * (1.0 - r)).powi(4),
+            Shape::Step if 0.25 <= r && r <= 0.75 => 1.0,
+            Shape::Step => 0.0,
+        }
+    }
+
+    /// Maps the neighbourhood potential `u` to a growth rate in `[-1, 1]`.
+    fn growth(&self, u: f64, mu: f64, sigma: f64) -> f64 {
+        let d = u - mu;
+        match *self {
+            Shape::Exponential => 2.0 * (-d * d / (2.0 * sigma * sigma)).exp() - 1.0,
+            Shape::Polynomial => {
+                2.0 * (1.0 - d * d / (9.0 * sigma * sigma)).max(0.0).powi(4) - 1.0
+            }
+            Shape::Step if d.abs() <= sigma => 1.0,
+            Shape::Step => -1.0,
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Shape, String> {
+        match s {
+            "exp" => Ok(Shape::Exponential),
+            "poly" => Ok(Shape::Polynomial),
+            "step" => Ok(Shape::Step),
+            _ => Err(format!("unknown shape `{}`, expected exp, poly or step", s)),
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Shape::Exponential => "exp",
+            Shape::Polynomial => "poly",
+            Shape::Step => "step",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A continuous-state automaton whose cells hold values in `[0, 1]`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContinuousRule {
+    /// Lenia: the potential is the convolution with a kernel of concentric shells of the given
+    /// peak heights, and cells grow by `growth(potential) / steps` each generation.
+    Lenia {
+        radius: u32,
+        steps: u32,
+        mu: f64,
+        sigma: f64,
+        peaks: Vec<f64>,
+        kernel: Shape,
+        growth: Shape,
+    },
+    /// SmoothLife: the filling of the inner disk (a third of the radius) selects between the
+    /// birth and death intervals for the filling of the surrounding annulus. Cells move towards
+    /// the result by `dt`, so a `dt` of 1 gives the discrete-time SmoothLife.
+    SmoothLife {
+        radius: u32,
+        birth: (f64, f64),
+        death: (f64, f64),
+        alpha_n: f64,
+        alpha_m: f64,
+        dt: f64,
+    },
+}
+
+impl Default for ContinuousRule {
+    /// The Lenia rule of the Orbium glider.
+    fn default() -> Self {
+        ContinuousRule::Lenia {
+            radius: 13,
+            steps: 10,
+            mu: 0.15,
+            sigma: 0.015,
+            peaks: vec![1.0],
+            kernel: Shape::Exponential,
+            growth: Shape::Exponential,
+        }
+    }
+}
+
+impl ContinuousRule {
+    /// The SmoothLife rule of Rafler's paper.
+    pub fn smooth_life() -> ContinuousRule {
+        ContinuousRule::SmoothLife {
+            radius: 12,
+            birth: (0.278, 0.365),
+            death: (0.267, 0.445),
+            alpha_n: 0.028,
+            alpha_m: 0.147,
+            dt: 1.0,
+        }
+    }
+
+    pub fn radius(&self) -> u32 {
+        match *self {
+            ContinuousRule::Lenia { radius, .. } |
+            ContinuousRule::SmoothLife { radius, .. } => radius,
+        }
+    }
+
+    /// Returns the convolution kernels as `(dx, dy, weight)`, each normalised to a sum of 1.
+    fn kernels(&self) -> Vec<Vec<(i32, i32, f64)>> {
+        match *self {
+            ContinuousRule::Lenia { radius, ref peaks, kernel, .. } => {
+                let r = radius as i32;
+                let mut taps = vec![];
+                for dy in -r..(r + 1) {
+                    for dx in -r..(r + 1) {
+                        let d = ((dx * dx + dy * dy) as f64).sqrt() / (radius as f64);
+                        if d >= 1.0 {
+                            continue;
+                        }
+                        let br = d * (peaks.len() as f64);
+                        let w = peaks[br.floor() as usize] * kernel.kernel_core(br.fract());
+                        if w > 0.0 {
+                            taps.push((dx, dy, w));
+                        }
+                    }
+                }
+                vec![normalise(taps)]
+            }
+            ContinuousRule::SmoothLife { radius, .. } => {
+                // Cells crossing the rims are weighted by how far they lie inside.
+                let (ra, ri) = (radius as f64, (radius as f64) / 3.0);
+                let r = radius as i32 + 1;
+                let (mut disk, mut annulus) = (vec![], vec![]);
+                for dy in -r..(r + 1) {
+                    for dx in -r..(r + 1) {
+                        let l = ((dx * dx + dy * dy) as f64).sqrt();
+                        let inner = clamp(ri + 0.5 - l);
+                        let outer = clamp(ra + 0.5 - l) - inner;
+                        if inner > 0.0 {
+                            disk.push((dx, dy, inner));
+                        }
+                        if outer > 0.0 {
+                            annulus.push((dx, dy, outer));
+                        }
+                    }
+                }
+                vec![normalise(disk), normalise(annulus)]
+            }
+        }
+    }
+
+    /// Returns the next value of a cell from its value and its convolutions with the kernels.
+    fn next_value(&self, value: f32, sums: &[f32]) -> f32 {
+        let value = value as f64;
+        let next = match *self {
+            ContinuousRule::Lenia { steps, mu, sigma, growth, .. } => {
+                value + growth.growth(sums[0] as f64, mu, sigma) / (steps as f64)
+            }
+            ContinuousRule::SmoothLife { birth, death, alpha_n, alpha_m, dt, .. } => {
+                let (m, n) = (sums[0] as f64, sums[1] as f64);
+                let alive = sigmoid(m, 0.5, alpha_m);
+                let lo = birth.0 * (1.0 - alive) + death.0 * alive;
+                let hi = birth.1 * (1.0 - alive) + death.1 * alive;
+                let s = sigmoid(n, lo, alpha_n) * (1.0 - sigmoid(n, hi, alpha_n));
+                value + dt * (s - value)
+            }
+        };
+        clamp(next) as f32
+    }
+
+    fn set_param(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match *self {
+            ContinuousRule::Lenia { ref mut radius,
+                                    ref mut steps,
+                                    ref mut mu,
+                                    ref mut sigma,
+                                    ref mut peaks,
+                                    ref mut kernel,
+                                    ref mut growth } => {
+                match key {
+                    "R" => *radius = parse_param(key, value)?,
+                    "T" => *steps = parse_param(key, value)?,
+                    "m" => *mu = parse_param(key, value)?,
+                    "s" => *sigma = parse_param(key, value)?,
+                    "b" => {
+                        *peaks = value.split('/')
+                            .map(|v| parse_param(key, v))
+                            .collect::<Result<_, _>>()?
+                    }
+                    "k" => *kernel = value.parse()?,
+                    "g" => *growth = value.parse()?,
+                    _ => return Err(format!("unknown Lenia parameter `{}`", key)),
+                }
+            }
+            ContinuousRule::SmoothLife { ref mut radius,
+                                         ref mut birth,
+                                         ref mut death,
+                                         ref mut alpha_n,
+                                         ref mut alpha_m,
+                                         ref mut dt } => {
+                match key {
+                    "R" => *radius = parse_param(key, value)?,
+                    "b" => *birth = parse_interval(key, value)?,
+                    "d" => *death = parse_interval(key, value)?,
+                    "an" => *alpha_n = parse_param(key, value)?,
+                    "am" => *alpha_m = parse_param(key, value)?,
+                    "dt" => *dt = parse_param(key, value)?,
+                    _ => return Err(format!("unknown SmoothLife parameter `{}`", key)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let min_radius = match *self {
+            ContinuousRule::Lenia { steps, sigma, ref peaks, .. } => {
+                if steps == 0 || !(sigma > 0.0) {
+                    return Err("T and s must be positive".to_string());
+                }
+                if peaks.is_empty() || peaks.iter().any(|&b| !(0.0 <= b && b <= 1.0)) {
+                    return Err("kernel peaks must lie between 0 and 1".to_string());
+                }
+                1
+            }
+            ContinuousRule::SmoothLife { alpha_n, alpha_m, dt, .. } => {
+                if !(alpha_n > 0.0 && alpha_m > 0.0) {
+                    return Err("an and am must be positive".to_string());
+                }
+                if !(0.0 < dt && dt <= 1.0) {
+                    return Err("dt must lie between 0 and 1".to_string());
+                }
+                3
+            }
+        };
+        let radius = self.radius();
+        if radius < min_radius || radius > MAX_RADIUS {
+            return Err(format!("radius must lie between {} and {}", min_radius, MAX_RADIUS));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ContinuousRule {
+    type Err = String;
+
+    /// Parses `Lenia` or `SmoothLife`, optionally followed by `:` and comma-separated
+    /// `key=value` parameters, e.g. `Lenia:R=13,T=10,m=0.15,s=0.015,b=1,k=exp,g=exp` or
+    /// `SmoothLife:R=12,b=0.278/0.365,d=0.267/0.445,an=0.028,am=0.147,dt=1`.
+    /// Omitted parameters keep the values shown.
+    fn from_str(s: &str) -> Result<ContinuousRule, String> {
+        let s = s.trim();
+        let mut parts = s.splitn(2, ':');
+        let mut rule = match parts.next() {
+            Some("Lenia") => ContinuousRule::default(),
+            Some("SmoothLife") => ContinuousRule::smooth_life(),
+            _ => return Err(format!("invalid rule: {}", s)),
+        };
+        for param in parts.next().into_iter().flat_map(|params| params.split(',')) {
+            let mut it = param.splitn(2, '=').map(str::trim);
+            let result = match (it.next(), it.next()) {
+                (Some(key), Some(value)) => rule.set_param(key, value),
+                _ => Err(format!("expected `key=value`, found `{}`", param)),
+            };
+            result.map_err(|e| format!("invalid rule {}: {}", s, e))?;
+        }
+        rule.validate().map_err(|e| format!("invalid rule {}: {}", s, e))?;
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for ContinuousRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContinuousRule::Lenia { radius, steps, mu, sigma, ref peaks, kernel, growth } => {
+                let peaks = peaks.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+                write!(f,
+                       "Lenia:R={},T={},m={},s={},b={},k={},g={}",
+                       radius,
+                       steps,
+                       mu,
+                       sigma,
+                       peaks.join("/"),
+                       kernel,
+                       growth)
+            }
+            ContinuousRule::SmoothLife { radius, birth, death, alpha_n, alpha_m, dt } => {
+                write!(f,
+                       "SmoothLife:R={},b={}/{},d={}/{},an={},am={},dt={}",
+                       radius,
+                       birth.0,
+                       birth.1,
+                       death.0,
+                       death.1,
+                       alpha_n,
+                       alpha_m,
+                       dt)
+            }
+        }
+    }
+}
+
+/// A board of real-valued cells that wraps around at the edges, as in the reference
+/// implementations of Lenia and SmoothLife.
+///
+/// Cell states are the values scaled to `0..255`.
+#[derive(Clone, Debug)]
+pub struct ContinuousBoard {
+    size: Size,
+    cells: Vec<f32>,
+    // The cells surrounded by `pad` wrapped-around rows and columns.
+    padded: Vec<f32>,
+    pad: i32,
+    rule: ContinuousRule,
+    // Kernel taps as offsets into `padded` and weights.
+    kernels: Vec<Vec<(isize, f32)>>,
+}
+
+impl ContinuousBoard {
+    pub fn new_empty(size: Size, rule: ContinuousRule) -> Self {
+        let pad = rule.radius() as i32 + 1;
+        let width = (size.0 + 2 * pad) as isize;
+        let kernels = rule.kernels()
+            .into_iter()
+            .map(|taps| {
+                taps.into_iter()
+                    .map(|(dx, dy, w)| ((dy as isize) * width + (dx as isize), w as f32))
+                    .collect()
+            })
+            .collect();
+        let len = (size.0 * size.1) as usize;
+        ContinuousBoard {
+            size: size,
+            cells: vec![0.0; len],
+            padded: vec![0.0; ((size.0 + 2 * pad) * (size.1 + 2 * pad)) as usize],
+            pad: pad,
+            rule: rule,
+            kernels: kernels,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn rule(&self) -> &ContinuousRule {
+        &self.rule
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        0 <= p.0 && p.0 < self.size.0 && 0 <= p.1 && p.1 < self.size.1
+    }
+
+    pub fn state(&self, p: Point) -> u8 {
+        to_state(self.cells[self.index(p)])
+    }
+
+    pub fn set_state(&mut self, p: Point, state: u8) {
+        let i = self.index(p);
+        self.cells[i] = (state as f32) / 255.0;
+    }
+
+    pub fn clear(&mut self) {
+        for v in &mut self.cells {
+            *v = 0.0;
+        }
+    }
+
+    /// Scatters squares of random values, each twice the kernel radius wide.
+    pub fn random_init<R>(&mut self, rng: &mut R)
+        where R: Rng
+    {
+        self.clear();
+        if self.cells.is_empty() {
+            return;
+        }
+        let side = 2 * self.rule.radius() as i32;
+        let count = cmp::max(self.cells.len() / (4 * (side * side) as usize), 1);
+        for _ in 0..count {
+            let origin = Point(rng.gen_range(0, self.size.0), rng.gen_range(0, self.size.1));
+            for y in 0..side {
+                for x in 0..side {
+                    let p = Point((origin.0 + x) % self.size.0, (origin.1 + y) % self.size.1);
+                    let i = self.index(p);
+                    self.cells[i] = rng.gen();
+                }
+            }
+        }
+    }
+
+    /// Returns the number of cells whose state is not 0.
+    pub fn population(&self) -> u32 {
+        self.cells.iter().filter(|&&v| to_state(v) != 0).count() as u32
+    }
+
+    pub fn count_in_rect(&self, p: Point, size: Size) -> u32 {
+        let x0 = cmp::max(p.0, 0);
+        let x1 = cmp::min(p.0 + size.0, self.size.0);
+        let mut count = 0;
+        for y in cmp::max(p.1, 0)..cmp::min(p.1 + size.1, self.size.1) {
+            for x in x0..x1 {
+                if self.state(Point(x, y)) != 0 {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    pub fn grow(&mut self) {
+        let Size(w, h) = self.size;
+        let pad = self.pad;
+        let width = w + 2 * pad;
+        for py in 0..(h + 2 * pad) {
+            let y = wrap(py - pad, h);
+            for px in 0..width {
+                let x = wrap(px - pad, w);
+                self.padded[(py * width + px) as usize] = self.cells[(y * w + x) as usize];
+            }
+        }
+
+        let mut sums = vec![0.0; self.kernels.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let base = ((y + pad) * width + x + pad) as isize;
+                for (sum, taps) in sums.iter_mut().zip(&self.kernels) {
+                    *sum = taps.iter()
+                        .map(|&(offset, weight)| weight * self.padded[(base + offset) as usize])
+                        .sum();
+                }
+                let i = (y * w + x) as usize;
+                self.cells[i] = self.rule.next_value(self.cells[i], &sums);
+            }
+        }
+    }
+
+    fn index(&self, p: Point) -> usize {
+        (p.1 * self.size.0 + p.0) as usize
+    }
+}
+
+/// Maps a state to a colour running from black through purple and orange to pale yellow.
+pub fn color_map(state: u8) -> [u8; 4] {
+    let t = (state as f64) / 255.0 * ((COLOR_MAP.len() - 1) as f64);
+    let i = cmp::min(t.floor() as usize, COLOR_MAP.len() - 2);
+    let (a, b) = (COLOR_MAP[i], COLOR_MAP[i + 1]);
+    let mix = |k: usize| ((a[k] as f64) + ((b[k] as f64) - (a[k] as f64)) * (t - i as f64)).round();
+    [mix(0) as u8, mix(1) as u8, mix(2) as u8, 255]
+}
+
+fn to_state(value: f32) -> u8 {
+    (value * 255.0).round() as u8
+}
+
+fn wrap(n: i32, len: i32) -> i32 {
+    ((n % len) + len) % len
+}
+
+fn clamp(v: f64) -> f64 {
+    v.max(0.0).min(1.0)
+}
+
+fn sigmoid(x: f64, a: f64, alpha: f64) -> f64 {
+    1.0 / (1.0 + (-(x - a) * 4.0 / alpha).exp())
+}
+
+fn normalise(taps: Vec<(i32, i32, f64)>) -> Vec<(i32, i32, f64)> {
+    let total = taps.iter().map(|t| t.2).sum::<f64>();
+    taps.into_iter().map(|(dx, dy, w)| (dx, dy, w / total)).collect()
+}
+
+fn parse_param<T>(key: &str, value: &str) -> Result<T, String>
+    where T: FromStr
+{
+    value.parse().map_err(|_| format!("invalid value for `{}`: {}", key, value))
+}
+
+fn parse_interval(key: &str, value: &str) -> Result<(f64, f64), String> {
+    let mut it = value.splitn(2, '/');
+    match (it.next(), it.next()) {
+        (Some(lo), Some(hi)) => Ok((parse_param(key, lo)?, parse_param(key, hi)?)),
+        _ => Err(format!("expected `lo/hi` for `{}`: {}", key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    #[test]
+    fn round_trip() {
+        let lenia = "Lenia:R=20,T=5,m=0.2,s=0.03,b=1/0.5,k=poly,g=step";
+        let rule = lenia.parse::<ContinuousRule>().unwrap();
+        assert_eq!(rule,
+                   ContinuousRule::Lenia {
+                       radius: 20,
+                       steps: 5,
+                       mu: 0.2,
+                       sigma: 0.03,
+                       peaks: vec![1.0, 0.5],
+                       kernel: Shape::Polynomial,
+                       growth: Shape::Step,
+                   });
+        assert_eq!(rule.to_string(), lenia);
+
+        let smooth_life = "SmoothLife:R=10,b=0.25/0.35,d=0.2/0.45,an=0.03,am=0.15,dt=0.5";
+        let rule = smooth_life.parse::<ContinuousRule>().unwrap();
+        assert_eq!(rule,
+                   ContinuousRule::SmoothLife {
+                       radius: 10,
+                       birth: (0.25, 0.35),
+                       death: (0.2, 0.45),
+                       alpha_n: 0.03,
+                       alpha_m: 0.15,
+                       dt: 0.5,
+                   });
+        assert_eq!(rule.to_string(), smooth_life);
+
+        assert_eq!("Lenia".parse(), Ok(ContinuousRule::default()));
+        assert_eq!("SmoothLife".parse(), Ok(ContinuousRule::smooth_life()));
+        let default = ContinuousRule::default();
+        assert_eq!(default.to_string().parse(), Ok(default));
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        for s in &["Lenia:T=0",
+                   "Lenia:s=0",
+                   "Lenia:b=2",
+                   "Lenia:b=",
+                   "Lenia:R=51",
+                   "Lenia:R=0",
+                   "Lenia:k=cube",
+                   "Lenia:x=1",
+                   "Lenia:an=0.1",
+                   "Lenia:R",
+                   "SmoothLife:dt=0",
+                   "SmoothLife:dt=1.5",
+                   "SmoothLife:an=0",
+                   "SmoothLife:R=2",
+                   "SmoothLife:b=0.3",
+                   "SmoothLife:T=10",
+                   "Smoothlife"] {
+            assert!(s.parse::<ContinuousRule>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn kernels_are_normalised() {
+        let rules = [ContinuousRule::default(),
+                     "Lenia:R=7,b=1/0.5/0.25,k=step".parse().unwrap(),
+                     ContinuousRule::smooth_life()];
+        for rule in &rules {
+            for taps in rule.kernels() {
+                let sum = taps.iter().map(|&(_, _, w)| w).sum::<f64>();
+                assert!((sum - 1.0).abs() < 1e-9, "{}: {}", rule, sum);
+            }
+        }
+    }
+
+    #[test]
+    fn grow() {
+        for s in &["Lenia:R=5", "SmoothLife:R=6,dt=0.5"] {
+            let mut board = ContinuousBoard::new_empty(Size(24, 20), s.parse().unwrap());
+            board.grow();
+            assert_eq!(board.population(), 0, "{}", s);
+
+            board.random_init(&mut XorShiftRng::from_seed([1, 2, 3, 4]));
+            let before = board.cells.clone();
+            board.grow();
+            assert!(board.cells.iter().all(|v| (0.0..=1.0).contains(v)), "{}", s);
+            assert!(board.cells != before, "{}", s);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use gif::{self, SetParameter};
+use render::Canvas;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use time;
+
+pub fn save_png(canvas: &Canvas, path: &Path) -> io::Result<()> {
+    canvas.save(path)
+}
+
+/// Returns a file name like `<prefix>-20170601-123456.<ext>` in the current directory.
+pub fn timestamped_path(prefix: &str, ext: &str) -> PathBuf {
+    let now = time::now();
+    let stamp = now.strftime("%Y%m%d-%H%M%S")
+        .map(|s| s.to_string())
+        .unwrap_or_else(|_| "0".to_string());
+    PathBuf::from(format!("{}-{}.{}", prefix, stamp, ext))
+}
+
+enum Output {
+    Gif(Option<gif::Encoder<BufWriter<File>>>),
+    PngSequence(u32),
+}
+
+/// Writes rendered generations to an animated GIF or a numbered PNG sequence.
+///
+/// The format is chosen from the extension of the path: `foo.gif` produces a single animated
+/// GIF, `foo.png` produces `foo-000000.png`, `foo-000001.png`, ...
+pub struct Recorder {
+    path: PathBuf,
+    output: Output,
+    size: Option<(u32, u32)>,
+    delay: u16,
+}
+
+impl Recorder {
+    /// Creates a recorder. `delay` is the time between GIF frames in 1/100 seconds.
+    pub fn new<P>(path: P, delay: u16) -> io::Result<Recorder>
+        where P: Into<PathBuf>
+    {
+        let path = path.into();
+        let output = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gif") => Output::Gif(None),
+            Some("png") => Output::PngSequence(0),
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("unsupported recording format: {}",
+                                                  path.display())))
+            }
+        };
+
+        Ok(Recorder {
+            path: path,
+            output: output,
+            size: None,
+            delay: delay,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn add_frame(&mut self, canvas: &Canvas) -> io::Result<()> {
+        let size = canvas.dimensions();
+        if *self.size.get_or_insert(size) != size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "frame size changed during recording"));
+        }
+
+        match self.output {
+            Output::Gif(ref mut encoder) => {
+                if encoder.is_none() {
+                    if size.0 > u16::max_value() as u32 || size.1 > u16::max_value() as u32 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  "image is too large for GIF"));
+                    }
+                    let file = BufWriter::new(File::create(&self.path)?);
+                    let mut e = gif::Encoder::new(file, size.0 as u16, size.1 as u16, &[])?;
+                    e.set(gif::Repeat::Infinite)?;
+                    *encoder = Some(e);
+                }
+                let mut frame = gif_frame(canvas);
+                frame.delay = self.delay;
+                encoder.as_mut().unwrap().write_frame(&frame)
+            }
+            Output::PngSequence(ref mut index) => {
+                let stem = self.path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+                let path = self.path.with_file_name(format!("{}-{:06}.png", stem, index));
+                *index += 1;
+                save_png(canvas, &path)
+            }
+        }
+    }
+}
+
+/// Converts a canvas into a GIF frame, using an exact palette when it has at most 256 colors.
+fn gif_frame(canvas: &Canvas) -> gif::Frame<'static> {
+    let (width, height) = canvas.dimensions();
+    let mut palette = vec![];
+    let mut indices = HashMap::new();
+    let mut buffer = Vec::with_capacity((width * height) as usize);
+
+    for pixel in canvas.pixels() {
+        let rgb = [pixel[0], pixel[1], pixel[2]];
+        let next = indices.len();
+        let index = *indices.entry(rgb).or_insert_with(|| {
+            palette.extend_from_slice(&rgb);
+            next
+        });
+        if index > 255 {
+            let mut pixels = canvas.clone().into_raw();
+            return gif::Frame::from_rgba(width as u16, height as u16, &mut pixels);
+        }
+        buffer.push(index as u8);
+    }
+
+    let mut frame = gif::Frame::default();
+    frame.width = width as u16;
+    frame.height = height as u16;
+    frame.palette = Some(palette);
+    frame.buffer = buffer.into();
+    frame
+}
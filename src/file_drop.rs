@@ -0,0 +1,78 @@
+use geom::Point;
+use sdl2::libc::{c_int, c_void};
+use sdl2::sys::event::{SDL_DROPFILE, SDL_AddEventWatch, SDL_DelEventWatch, SDL_Event};
+use sdl2_window::Sdl2Window;
+use std::ffi::CStr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+extern "C" {
+    fn SDL_GetGlobalMouseState(x: *mut c_int, y: *mut c_int) -> u32;
+}
+
+/// Collects the files dropped onto the window.
+///
+/// Piston's SDL2 backend discards `SDL_DROPFILE` events, so they are picked up by an event
+/// watch as SDL queues them, and handed out by `take`.
+pub struct FileDrops {
+    queue: Box<Mutex<Vec<PathBuf>>>,
+}
+
+impl FileDrops {
+    pub fn new() -> FileDrops {
+        let drops = FileDrops { queue: Box::new(Mutex::new(vec![])) };
+        unsafe {
+            SDL_AddEventWatch(watch, drops.userdata());
+        }
+        drops
+    }
+
+    /// Returns the files dropped since the last call.
+    pub fn take(&self) -> Vec<PathBuf> {
+        let mut queue = self.queue.lock().unwrap();
+        queue.drain(..).collect()
+    }
+
+    fn userdata(&self) -> *mut c_void {
+        &*self.queue as *const Mutex<Vec<PathBuf>> as *mut c_void
+    }
+}
+
+impl Drop for FileDrops {
+    fn drop(&mut self) {
+        unsafe {
+            SDL_DelEventWatch(watch, self.userdata());
+        }
+    }
+}
+
+extern "C" fn watch(userdata: *mut c_void, event: *mut SDL_Event) -> c_int {
+    unsafe {
+        if *(*event).type_() != SDL_DROPFILE {
+            return 0;
+        }
+        let file = (*(*event).drop()).file;
+        if file.is_null() {
+            return 0;
+        }
+        let path = PathBuf::from(CStr::from_ptr(file).to_string_lossy().into_owned());
+        let queue = &*(userdata as *const Mutex<Vec<PathBuf>>);
+        if let Ok(mut queue) = queue.lock() {
+            queue.push(path);
+        }
+    }
+    0
+}
+
+/// Returns the position of the mouse cursor relative to the window.
+///
+/// The window gets no motion events while a file is dragged over it, so this asks for the
+/// position on the screen instead of using the last known one.
+pub fn cursor_position(window: &Sdl2Window) -> Point {
+    let (mut x, mut y) = (0, 0);
+    unsafe {
+        SDL_GetGlobalMouseState(&mut x, &mut y);
+    }
+    let (wx, wy) = window.window.position();
+    Point(x - wx, y - wy)
+}
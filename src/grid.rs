@@ -0,0 +1,331 @@
+use geom::{Point, Size};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GridKind {
+    Plane,
+    Torus,
+    KleinBottle,
+    CrossSurface,
+    Sphere,
+}
+
+/// A bounded grid in Golly's notation, such as `T100,80`, `K30*,20` or `S50`, which fixes the
+/// size of the board and how its edges are joined.
+///
+/// A twisted pair of edges is joined in reverse, and a shifted pair is joined with the cells on
+/// one side moved along the edge. The twist and the shift are written on the dimension along
+/// the joined edges: `K30*,20` twists the top and bottom edges, and `T100+5,80` moves a glider
+/// that leaves through the bottom 5 cells to the right as it enters at the top.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BoundedGrid {
+    kind: GridKind,
+    size: Size,
+    twist: (bool, bool),
+    shift: (i32, i32),
+}
+
+impl BoundedGrid {
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Returns the cell on the grid that `p` refers to, or `None` if `p` lies beyond an edge
+    /// that is not joined to another one.
+    pub fn map(&self, p: Point) -> Option<Point> {
+        let Size(w, h) = self.size;
+        let Point(mut x, mut y) = p;
+        let inside = |x: i32, y: i32| 0 <= x && x < w && 0 <= y && y < h;
+        match self.kind {
+            GridKind::Plane => {
+                return if inside(x, y) { Some(p) } else { None };
+            }
+            GridKind::Sphere => {
+                // The top edge is joined to the left edge and the right edge to the bottom
+                // edge, so cells beyond them are mirrored across the diagonal.
+                for _ in 0..4 {
+                    let (nx, ny) = if x < 0 {
+                        (y, -x - 1)
+                    } else if x >= w {
+                        (y, 2 * w - 1 - x)
+                    } else if y < 0 {
+                        (-y - 1, x)
+                    } else if y >= h {
+                        (2 * h - 1 - y, x)
+                    } else {
+                        return Some(Point(x, y));
+                    };
+                    x = nx;
+                    y = ny;
+                }
+                return None;
+            }
+            _ => {}
+        }
+
+        let cross = self.kind == GridKind::CrossSurface;
+        let (twist_x, twist_y) = (self.twist.0 || cross, self.twist.1 || cross);
+        while !inside(x, y) {
+            if x < 0 || x >= w {
+                let right = x >= w;
+                x += if right { -w } else { w };
+                y = if twist_y {
+                    h - 1 + self.shift.1 - y
+                } else if right {
+                    y + self.shift.1
+                } else {
+                    y - self.shift.1
+                };
+            } else {
+                let down = y >= h;
+                y += if down { -h } else { h };
+                x = if twist_x {
+                    w - 1 + self.shift.0 - x
+                } else if down {
+                    x + self.shift.0
+                } else {
+                    x - self.shift.0
+                };
+            }
+        }
+        Some(Point(x, y))
+    }
+}
+
+impl FromStr for BoundedGrid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BoundedGrid, String> {
+        let s = s.trim();
+        let invalid = || format!("invalid bounded grid: {}", s);
+        let mut chars = s.chars();
+        let kind = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('P') => GridKind::Plane,
+            Some('T') => GridKind::Torus,
+            Some('K') => GridKind::KleinBottle,
+            Some('C') => GridKind::CrossSurface,
+            Some('S') => GridKind::Sphere,
+            _ => return Err(invalid()),
+        };
+
+        let dims = chars.as_str()
+            .split(',')
+            .map(parse_dim)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(&invalid)?;
+        let (x, y) = match (kind, dims.len()) {
+            (GridKind::Sphere, 1) => (dims[0], dims[0]),
+            (GridKind::Sphere, _) => return Err(invalid()),
+            (_, 2) => (dims[0], dims[1]),
+            _ => return Err(invalid()),
+        };
+
+        let grid = BoundedGrid {
+            kind: kind,
+            size: Size(x.0, y.0),
+            twist: (x.1, y.1),
+            shift: (x.2, y.2),
+        };
+        let twists = (x.1 as u32) + (y.1 as u32);
+        let shifts = (x.2 != 0) as u32 + (y.2 != 0) as u32;
+        let valid = match kind {
+            GridKind::Torus => twists == 0 && shifts <= 1,
+            GridKind::KleinBottle => twists == 1 && shifts <= 1,
+            _ => twists == 0 && shifts == 0,
+        };
+        if !valid {
+            return Err(invalid());
+        }
+        if x.0 <= 0 || y.0 <= 0 {
+            return Err(format!("unbounded grids are not supported: {}", s));
+        }
+        if x.2.abs() >= x.0 || y.2.abs() >= y.0 {
+            return Err(format!("shift must be smaller than the edge: {}", s));
+        }
+        Ok(grid)
+    }
+}
+
+impl fmt::Display for BoundedGrid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            GridKind::Plane => "P",
+            GridKind::Torus => "T",
+            GridKind::KleinBottle => "K",
+            GridKind::CrossSurface => "C",
+            GridKind::Sphere => "S",
+        };
+        if self.kind == GridKind::Sphere {
+            return write!(f, "{}{}", kind, self.size.0);
+        }
+        write!(f, "{}", kind)?;
+        write_dim(f, self.size.0, self.twist.0, self.shift.0)?;
+        write!(f, ",")?;
+        write_dim(f, self.size.1, self.twist.1, self.shift.1)
+    }
+}
+
+/// Parses a size written as `WxH`.
+pub fn parse_size(s: &str) -> Result<Size, String> {
+    let mut it = s.splitn(2, 'x');
+    let size = match (it.next().map(str::parse), it.next().map(str::parse)) {
+        (Some(Ok(w)), Some(Ok(h))) if w > 0 && h > 0 => Size(w, h),
+        _ => return Err(format!("invalid size: {}", s)),
+    };
+    Ok(size)
+}
+
+/// Parses `30`, `30*`, `30+5` or `30*-1` into the size, twist and shift.
+fn parse_dim(s: &str) -> Option<(i32, bool, i32)> {
+    let s = s.trim();
+    let end = s.find(|c: char| !c.is_digit(10)).unwrap_or(s.len());
+    let size = s[..end].parse().ok()?;
+    let mut rest = &s[end..];
+    let twist = rest.starts_with('*');
+    if twist {
+        rest = &rest[1..];
+    }
+    let shift = match rest.chars().next() {
+        None => 0,
+        Some('+') => rest[1..].parse().ok()?,
+        Some('-') => rest.parse().ok()?,
+        _ => return None,
+    };
+    Some((size, twist, shift))
+}
+
+fn write_dim(f: &mut fmt::Formatter, size: i32, twist: bool, shift: i32) -> fmt::Result {
+    write!(f, "{}", size)?;
+    if twist {
+        write!(f, "*")?;
+    }
+    if shift != 0 {
+        write!(f, "{:+}", shift)?;
+    }
+    Ok(())
+}
+
+/// What the cells just outside of a board count as, except at edges joined by a bounded grid.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EdgeMode {
+    /// Dead.
+    Absorbing,
+    /// Mirror images of the cells inside.
+    Reflecting,
+    /// Alive.
+    Alive,
+    /// Alive with the given probability, drawn anew each generation.
+    Noisy(f64),
+}
+
+impl Default for EdgeMode {
+    fn default() -> Self {
+        EdgeMode::Absorbing
+    }
+}
+
+impl EdgeMode {
+    /// Returns the mode after this one, for cycling through them.
+    pub fn next(&self) -> EdgeMode {
+        match *self {
+            EdgeMode::Absorbing => EdgeMode::Reflecting,
+            EdgeMode::Reflecting => EdgeMode::Alive,
+            EdgeMode::Alive => EdgeMode::Noisy(0.5),
+            EdgeMode::Noisy(_) => EdgeMode::Absorbing,
+        }
+    }
+}
+
+impl FromStr for EdgeMode {
+    type Err = String;
+
+    /// Parses `absorbing`, `reflecting`, `alive` or `noisy`, which may be followed by the
+    /// density as in `noisy:0.2`.
+    fn from_str(s: &str) -> Result<EdgeMode, String> {
+        let s = s.trim();
+        let invalid = || format!("invalid edge mode: {}", s);
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("absorbing"), None) => Ok(EdgeMode::Absorbing),
+            (Some("reflecting"), None) => Ok(EdgeMode::Reflecting),
+            (Some("alive"), None) => Ok(EdgeMode::Alive),
+            (Some("noisy"), None) => Ok(EdgeMode::Noisy(0.5)),
+            (Some("noisy"), Some(density)) => {
+                match density.parse() {
+                    Ok(density) if 0.0 <= density && density <= 1.0 => Ok(EdgeMode::Noisy(density)),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for EdgeMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EdgeMode::Absorbing => write!(f, "absorbing"),
+            EdgeMode::Reflecting => write!(f, "reflecting"),
+            EdgeMode::Alive => write!(f, "alive"),
+            EdgeMode::Noisy(density) => write!(f, "noisy:{}", density),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_grid_round_trip() {
+        for s in &["P40,30", "T100,80", "T100+5,80", "T100,80-3", "K30*,20", "K30,20*+2",
+                   "C20,10", "S50"] {
+            let grid = s.parse::<BoundedGrid>().unwrap();
+            assert_eq!(grid.to_string(), *s);
+            assert_eq!(grid.to_string().parse::<BoundedGrid>().unwrap(), grid);
+        }
+        assert_eq!("t10, 20".parse::<BoundedGrid>().unwrap().to_string(), "T10,20");
+    }
+
+    #[test]
+    fn reject_malformed_bounded_grids() {
+        for s in &["", "X10,10", "T10", "T10,10,10", "T0,10", "Tx,10", "T10*,10", "T10+1,10+1",
+                   "T10+10,10", "K10,10", "K10*,10*", "C10*,10", "P10+1,10", "S10,10"] {
+            assert!(s.parse::<BoundedGrid>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn map_across_edges() {
+        let torus = "T10+2,8".parse::<BoundedGrid>().unwrap();
+        assert_eq!(torus.map(Point(3, 4)), Some(Point(3, 4)));
+        assert_eq!(torus.map(Point(-1, 4)), Some(Point(9, 4)));
+        assert_eq!(torus.map(Point(3, 8)), Some(Point(5, 0)));
+        assert_eq!(torus.map(Point(3, -1)), Some(Point(1, 7)));
+
+        let klein = "K10*,8".parse::<BoundedGrid>().unwrap();
+        assert_eq!(klein.map(Point(2, 8)), Some(Point(7, 0)));
+        assert_eq!(klein.map(Point(10, 3)), Some(Point(0, 3)));
+
+        let sphere = "S10".parse::<BoundedGrid>().unwrap();
+        assert_eq!(sphere.map(Point(-1, 3)), Some(Point(3, 0)));
+        assert_eq!(sphere.map(Point(3, -1)), Some(Point(0, 3)));
+
+        let plane = "P10,8".parse::<BoundedGrid>().unwrap();
+        assert_eq!(plane.map(Point(10, 3)), None);
+    }
+
+    #[test]
+    fn edge_mode_round_trip() {
+        for s in &["absorbing", "reflecting", "alive", "noisy:0.5", "noisy:0.2", "noisy:1"] {
+            let edge = s.parse::<EdgeMode>().unwrap();
+            assert_eq!(edge.to_string(), *s);
+            assert_eq!(edge.to_string().parse::<EdgeMode>().unwrap(), edge);
+        }
+        assert_eq!("noisy".parse::<EdgeMode>().unwrap(), EdgeMode::Noisy(0.5));
+        for s in &["", "wrapping", "alive:1", "noisy:", "noisy:1.5", "noisy:-0.1", "noisy:x"] {
+            assert!(s.parse::<EdgeMode>().is_err(), "{}", s);
+        }
+    }
+}
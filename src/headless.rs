@@ -0,0 +1,141 @@
+use export::{self, Recorder};
+use geom::{Point, Size};
+use grid::{self, EdgeMode};
+use pattern::Pattern;
+use rand;
+use render::{self, Aggregate};
+use std::cmp;
+use std::path::{Path, PathBuf};
+use world::{RuleSpec, World};
+
+pub const USAGE: &'static str = "\
+Usage: game-of-life-rs --headless [options]
+
+Options:
+    --size WxH           board size in cells (default: 256x192)
+    --generations N      number of generations to run (default: 100)
+    --cell-size N        pixels per cell, may be fractional (default: 2)
+    --rule RULE          rule such as B3/S23, B2/S/C3 or WireWorld, or a .rule file
+                         (default: the pattern's rule or B3/S23)
+    --edge MODE          cells beyond the edges: absorbing (default), reflecting, alive or
+                         noisy[:DENSITY]
+    --expand MAX_WxH     grow the board when cells near an edge, up to MAX_WxH cells
+    --pattern PATH       start from an RLE pattern instead of a random board
+    --density            shade zoomed-out pixels by density instead of any-alive
+    --record PATH        record every generation to PATH (.gif or numbered .png)
+    --delay N            GIF frame delay in 1/100 seconds (default: 5)
+    --screenshot PATH    save the final generation to PATH as PNG";
+
+pub struct Options {
+    board_size: Size,
+    generations: u64,
+    cell_size: f64,
+    rule: Option<RuleSpec>,
+    edge: EdgeMode,
+    max_size: Option<Size>,
+    pattern: Option<Pattern>,
+    aggregate: Aggregate,
+    record: Option<PathBuf>,
+    delay: u16,
+    screenshot: Option<PathBuf>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            board_size: Size(256, 192),
+            generations: 100,
+            cell_size: 2.0,
+            rule: None,
+            edge: EdgeMode::default(),
+            max_size: None,
+            pattern: None,
+            aggregate: Aggregate::AnyAlive,
+            record: None,
+            delay: 5,
+            screenshot: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for `{}`", arg))
+            };
+            match arg.as_str() {
+                "--size" => options.board_size = grid::parse_size(value()?)?,
+                "--generations" => options.generations = parse_num(arg, value()?)?,
+                "--cell-size" => options.cell_size = parse_num(arg, value()?)?,
+                "--rule" => options.rule = Some(value()?.parse()?),
+                "--edge" => options.edge = value()?.parse()?,
+                "--expand" => options.max_size = Some(grid::parse_size(value()?)?),
+                "--pattern" => options.pattern = Some(Pattern::load(Path::new(value()?))?),
+                "--density" => options.aggregate = Aggregate::Density,
+                "--record" => options.record = Some(PathBuf::from(value()?)),
+                "--delay" => options.delay = parse_num(arg, value()?)?,
+                "--screenshot" => options.screenshot = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("unknown option `{}`\n\n{}", arg, USAGE)),
+            }
+        }
+
+        if !(options.cell_size > 0.0) {
+            return Err("cell size must be positive".to_string());
+        }
+        Ok(options)
+    }
+}
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let mut board = match options.pattern {
+        Some(ref pattern) => {
+            let size = Size(cmp::max(options.board_size.0, pattern.size().0),
+                            cmp::max(options.board_size.1, pattern.size().1));
+            let rule = options.rule.as_ref().or(pattern.rule()).cloned().unwrap_or_default();
+            let mut board = World::new_empty(size, rule);
+            let size = board.size();
+            let origin = Point((size.0 - pattern.size().0) / 2, (size.1 - pattern.size().1) / 2);
+            pattern.draw(&mut board, origin);
+            board
+        }
+        None => {
+            let mut board =
+                World::new_empty(options.board_size, options.rule.clone().unwrap_or_default());
+            board.random_init(&mut rand::thread_rng());
+            board
+        }
+    };
+    board.set_edge(options.edge);
+    board.set_max_size(options.max_size);
+
+    let mut recorder = match options.record {
+        Some(ref path) => {
+            Some(Recorder::new(path.clone(), options.delay)
+                .map_err(|e| format!("{}: {}", path.display(), e))?)
+        }
+        None => None,
+    };
+
+    for generation in 0..(options.generations + 1) {
+        if generation > 0 {
+            board.grow();
+        }
+        if let Some(ref mut recorder) = recorder {
+            let canvas = render::render_board(&board, options.cell_size, options.aggregate);
+            recorder.add_frame(&canvas)
+                .map_err(|e| format!("{}: {}", recorder.path().display(), e))?;
+        }
+    }
+
+    if let Some(ref path) = options.screenshot {
+        let canvas = render::render_board(&board, options.cell_size, options.aggregate);
+        export::save_png(&canvas, path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+fn parse_num<T>(arg: &str, s: &str) -> Result<T, String>
+    where T: ::std::str::FromStr
+{
+    s.parse().map_err(|_| format!("invalid value for `{}`: {}", arg, s))
+}
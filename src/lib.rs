@@ -0,0 +1,29 @@
+//! The simulation and the viewer, shared by the native program and the WebAssembly build.
+
+extern crate board_game_geom as geom;
+extern crate deflate;
+#[cfg(not(target_arch = "wasm32"))]
+extern crate gif;
+extern crate image as im;
+extern crate inflate;
+extern crate rand;
+#[cfg(not(target_arch = "wasm32"))]
+extern crate time;
+
+pub mod app;
+pub mod board;
+pub mod continuous;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod export;
+pub mod grid;
+pub mod link;
+pub mod pattern;
+pub mod render;
+pub mod rule;
+pub mod rule_table;
+pub mod session;
+pub mod shape;
+pub mod state_board;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+pub mod world;
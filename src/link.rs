@@ -0,0 +1,223 @@
+use deflate;
+use geom::Move;
+use inflate;
+use pattern::Pattern;
+use std::fmt;
+use std::str;
+use world::RuleSpec;
+
+const BASE64: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Characters other than letters and digits that are left as they are in parameters.
+const UNESCAPED: &'static [u8] = b"-._~/:,*+!";
+
+/// A pattern and a view of it, written in the fragment of a link to the web version:
+///
+/// ```text
+/// #zoom=4&offset=12,-40&rle=q1CwVTDWUaiEUEWlOalAlpOxfrCRMVdSvooREBvnK3IBAA
+/// ```
+///
+/// `rle` is the pattern in RLE format, deflated and encoded in URL-safe Base64. The other
+/// parameters may be left out: `rule` overrides the rule of the pattern, `zoom` is the size of a
+/// cell in pixels and `offset` the position of the top-left corner of the pattern in the window.
+#[derive(Clone, Debug)]
+pub struct Link {
+    pub pattern: Pattern,
+    pub rule: Option<RuleSpec>,
+    pub scale: Option<f64>,
+    pub offset: Option<Move>,
+}
+
+impl Link {
+    pub fn parse(s: &str) -> Result<Link, String> {
+        let s = if s.starts_with('#') { &s[1..] } else { s };
+
+        let (mut pattern, mut rule, mut scale, mut offset) = (None, None, None, None);
+        for param in s.split('&').filter(|param| !param.is_empty()) {
+            let mut it = param.splitn(2, '=');
+            let (key, value) = match (it.next(), it.next()) {
+                (Some(key), Some(value)) => (key, percent_decode(value)?),
+                _ => return Err(format!("expected `key=value`: {}", param)),
+            };
+            let invalid = || format!("invalid {}: {}", key, value);
+            match key {
+                "rle" => {
+                    let data = base64_decode(&value).ok_or_else(&invalid)?;
+                    let rle = inflate::inflate_bytes(&data).map_err(|e| format!("rle: {}", e))?;
+                    let rle = String::from_utf8(rle).map_err(|_| invalid())?;
+                    pattern = Some(Pattern::parse_rle(&rle)?);
+                }
+                "rule" => rule = Some(value.parse()?),
+                "zoom" => {
+                    scale = match value.parse() {
+                        Ok(scale) if scale > 0.0 => Some(scale),
+                        _ => return Err(invalid()),
+                    }
+                }
+                "offset" => {
+                    let mut it = value.split(',').map(|n| n.trim().parse());
+                    offset = match (it.next(), it.next(), it.next()) {
+                        (Some(Ok(x)), Some(Ok(y)), None) => Some(Move(x, y)),
+                        _ => return Err(invalid()),
+                    }
+                }
+                _ => return Err(format!("unknown parameter `{}`", key)),
+            }
+        }
+        Ok(Link {
+            pattern: pattern.ok_or_else(|| "missing rle".to_string())?,
+            rule: rule,
+            scale: scale,
+            offset: offset,
+        })
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(scale) = self.scale {
+            write!(f, "zoom={}&", scale)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, "offset={},{}&", offset.0, offset.1)?;
+        }
+        if let Some(ref rule) = self.rule {
+            write!(f, "rule={}&", percent_encode(&rule.to_string()))?;
+        }
+        let data = deflate::deflate_bytes(self.pattern.to_rle().as_bytes());
+        write!(f, "rle={}", base64_encode(&data))
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..(chunk.len() + 1) {
+            out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let (mut n, mut bits) = (0u32, 0);
+    for c in s.trim_matches('=').bytes() {
+        let digit = match BASE64.iter().position(|&d| d == c) {
+            Some(digit) => digit as u32,
+            None => return None,
+        };
+        n = (n << 6 | digit) & 0x3fff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Escapes the characters that would end a parameter or the fragment.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || UNESCAPED.contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn percent_decode(s: &str) -> Result<String, String> {
+    let mut out = vec![];
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            out.push(b);
+            continue;
+        }
+        let hex = bytes.next().into_iter().chain(bytes.next()).collect::<Vec<_>>();
+        let byte = str::from_utf8(&hex).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) if hex.len() == 2 => out.push(byte),
+            _ => return Err(format!("invalid escape in {}", s)),
+        }
+    }
+    String::from_utf8(out).map_err(|_| format!("invalid escape in {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &'static str = "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!";
+
+    #[test]
+    fn round_trip() {
+        let link = Link {
+            pattern: Pattern::parse_rle(GLIDER).unwrap(),
+            rule: Some("B36/S23:T30,20".parse().unwrap()),
+            scale: Some(2.5),
+            offset: Some(Move(12, -40)),
+        };
+        let s = link.to_string();
+        assert!(s.starts_with("zoom=2.5&offset=12,-40&rule=B36/S23:T30,20&rle="), "{}", s);
+
+        let parsed = Link::parse(&format!("#{}", s)).unwrap();
+        assert_eq!(parsed.pattern.to_rle(), link.pattern.to_rle());
+        assert_eq!(parsed.rule.as_ref().map(|r| r.to_string()), Some("B36/S23:T30,20".to_string()));
+        assert_eq!(parsed.scale, Some(2.5));
+        assert_eq!(parsed.offset, Some(Move(12, -40)));
+        assert_eq!(parsed.to_string(), s);
+    }
+
+    #[test]
+    fn documented_example() {
+        let link = Link::parse("#zoom=4&offset=12,-40&rle=q1CwVTDWUaiEUEWlOalAlpOxfrCRMVdSvooRE\
+                                BvnK3IBAA")
+            .unwrap();
+        assert_eq!(link.pattern.to_rle(), Pattern::parse_rle(GLIDER).unwrap().to_rle());
+        assert!(link.rule.is_none());
+        assert_eq!(link.scale, Some(4.0));
+    }
+
+    #[test]
+    fn reject_malformed_links() {
+        let rle = base64_encode(&deflate::deflate_bytes(GLIDER.as_bytes()));
+        assert!(Link::parse(&format!("rle={}", rle)).is_ok());
+        for params in &["zoom=4", "zoom=0&rle=", "zoom=x&rle=", "offset=1&rle=",
+                        "offset=1,2,3&rle=", "rule=B9/S23&rle=", "size=3&rle=", "rle",
+                        "rule=B3%2&rle=", "rle=%%"] {
+            let s = if params.ends_with("rle=") {
+                format!("{}{}", params, rle)
+            } else {
+                params.to_string()
+            };
+            assert!(Link::parse(&s).is_err(), "{}", s);
+        }
+        assert!(Link::parse("rle=AAAA").is_err());
+        assert!(Link::parse("rle=ab$c").is_err());
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg");
+        assert_eq!(base64_encode(b"fo"), "Zm8");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(&[0xfb, 0xff]), "-_8");
+        for data in &[&b""[..], b"f", b"fo", b"foo", b"foob", &[0xfb, 0xff, 0x00, 0x80]] {
+            assert_eq!(base64_decode(&base64_encode(data)).unwrap(), data.to_vec());
+        }
+        assert_eq!(base64_decode("Zm9v==").unwrap(), b"foo".to_vec());
+
+        assert_eq!(percent_encode("B3/S23:T10,10 #&=é"), "B3/S23:T10,10%20%23%26%3D%C3%A9");
+        assert_eq!(percent_decode("B3/S23%3AT10%2c10%C3%A9").unwrap(), "B3/S23:T10,10é");
+        for s in &["%", "%4", "%zz", "%FF"] {
+            assert!(percent_decode(s).is_err(), "{}", s);
+        }
+    }
+}
@@ -1,23 +1,73 @@
 extern crate board_game_geom as geom;
+extern crate game_of_life_rs;
 extern crate graphics;
-extern crate image as im;
 extern crate opengl_graphics;
 extern crate piston;
+extern crate sdl2;
 extern crate sdl2_window;
 extern crate rand;
-extern crate time;
 
-use app::{App, AppSettings};
-use opengl_graphics::{GlGraphics, OpenGL};
-use piston::input::{Button, Input, Key, MouseButton, MouseCursorEvent, MouseScrollEvent, PressEvent,
-                    ReleaseEvent, RenderEvent, ResizeEvent, UpdateEvent};
+use app::{App, AppSettings, Touch};
+use bindings::{Action, Bindings};
+use file_drop::FileDrops;
+use game_of_life_rs::{app, export, grid, pattern, render, session, shape, world};
+use geom::Size;
+use grid::EdgeMode;
+use opengl_graphics::OpenGL;
+use pattern::Pattern;
+use session::Session;
+use piston::input::{Button, FocusEvent, Input, MouseCursorEvent, MouseScrollEvent, PressEvent,
+                    ReleaseEvent, RenderEvent, ResizeEvent, TouchEvent, UpdateEvent};
+use piston::input::keyboard::{ModifierKey, NO_MODIFIER};
 use piston::window::{OpenGLWindow, WindowSettings};
+use screen::Screen;
 use sdl2_window::Sdl2Window;
+use shape::Tool;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::process;
+use world::{RuleSpec, World};
 
-mod app;
-mod board;
+mod bindings;
+mod file_drop;
+mod headless;
+mod screen;
+
+struct Game {
+    app: App,
+    screen: Screen,
+    bindings: Bindings,
+    modifiers: ModifierKey,
+    held: HashMap<Button, Action>,
+    file_drops: FileDrops,
+    autosave: bool,
+}
 
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map_or(false, |arg| arg == "--headless") {
+        let result = headless::Options::parse(&args[1..]).and_then(|opts| headless::run(&opts));
+        if let Err(e) = result {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let args = match Args::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let bindings = Bindings::load().unwrap_or_else(|e| {
+        eprintln!("failed to load key bindings, using defaults: {}", e);
+        Bindings::default()
+    });
+
     let app_settings = AppSettings::default();
     let opengl = OpenGL::V2_1;
     let window: Sdl2Window = WindowSettings::new("Conway's Game of Life",
@@ -28,65 +78,115 @@ fn main() {
         .exit_on_esc(true)
         .build()
         .expect("failed to build Window");
-    let gl_graphics = GlGraphics::new(opengl);
 
-    let mut app = App::new(&app_settings, gl_graphics);
-    app.random_init(&mut rand::thread_rng());
+    let mut app = App::new(&app_settings);
+    if let Some(rule) = args.rule {
+        app.set_rule(rule);
+    }
+    if let Some(edge) = args.edge {
+        app.set_edge(edge);
+    }
+    app.set_max_size(args.max_size);
+    match args.pattern {
+        Some(pattern) => app.load_pattern(&pattern),
+        None => app.random_init(&mut rand::thread_rng()),
+    }
+    if let Some(ref session) = args.session {
+        app.restore(session);
+    }
+
+    let game = Game {
+        app: app,
+        screen: Screen::new(opengl),
+        bindings: bindings,
+        modifiers: NO_MODIFIER,
+        held: HashMap::new(),
+        file_drops: FileDrops::new(),
+        autosave: args.autosave,
+    };
+    event_loop::run(window, handle_event, handle_exit, game);
+}
+
+#[derive(Default)]
+struct Args {
+    rule: Option<RuleSpec>,
+    edge: Option<EdgeMode>,
+    max_size: Option<Size>,
+    pattern: Option<Pattern>,
+    session: Option<Session>,
+    autosave: bool,
+}
 
-    event_loop::run(window, handle_event, app);
+impl Args {
+    /// Parses `[--rule RULE] [--edge MODE] [--expand MAX_WxH] [--session PATH] [--autosave]
+    /// [PATTERN]`.
+    ///
+    /// With `--autosave` and neither a pattern nor a session, the session saved on the last
+    /// exit is reopened if there is one.
+    fn parse(args: &[String]) -> Result<Args, String> {
+        let mut result = Args::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for `{}`", arg))
+            };
+            match arg.as_str() {
+                "--rule" => result.rule = Some(value()?.parse()?),
+                "--edge" => result.edge = Some(value()?.parse()?),
+                "--expand" => result.max_size = Some(grid::parse_size(value()?)?),
+                "--session" => result.session = Some(Session::load(Path::new(value()?))?),
+                "--autosave" => result.autosave = true,
+                _ if result.pattern.is_none() && !arg.starts_with("--") => {
+                    result.pattern = Some(Pattern::load(Path::new(arg))?)
+                }
+                _ => return Err(format!("unexpected argument `{}`", arg)),
+            }
+        }
+        if result.autosave && result.pattern.is_none() && result.session.is_none() {
+            match Session::autosave_path() {
+                Some(ref path) if path.exists() => result.session = Some(Session::load(path)?),
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
 }
 
-fn handle_event(window: &mut Sdl2Window, e: Input, app: &mut App) {
-    if let Some(_args) = e.update_args() {
-        app.update();
+fn handle_event(window: &mut Sdl2Window, e: Input, game: &mut Game) {
+    let app = &mut game.app;
+
+    if let Some(args) = e.update_args() {
+        app.update(args.dt);
     }
 
     if let Some(size) = e.resize_args() {
         app.set_win_size(geom::Size(size[0] as i32, size[1] as i32));
     }
 
-    if let Some(Button::Keyboard(key)) = e.press_args() {
-        match key {
-            Key::R => app.random_init(&mut rand::thread_rng()),
-            Key::C => app.clear(),
-            Key::S => app.toggle_running(),
-            Key::F => app.fit_to_win_size(),
-            Key::Space => app.step(),
-            Key::Equals => app.zoom(1),
-            Key::Minus => app.zoom(-1),
-            Key::Right => app.slide(geom::Move(1, 0)),
-            Key::Left => app.slide(geom::Move(-1, 0)),
-            Key::Up => app.slide(geom::Move(0, -1)),
-            Key::Down => app.slide(geom::Move(0, 1)),
-            _ => {}
-        }
+    game.modifiers.event(&e);
+
+    for path in game.file_drops.take() {
+        let pos = file_drop::cursor_position(window);
+        app.drop_file(&path, pos);
     }
 
-    if let Some(Button::Keyboard(key)) = e.release_args() {
-        match key {
-            Key::Right => app.slide(geom::Move(-1, 0)),
-            Key::Left => app.slide(geom::Move(1, 0)),
-            Key::Up => app.slide(geom::Move(0, 1)),
-            Key::Down => app.slide(geom::Move(0, -1)),
-            _ => {}
+    if let Some(button) = e.press_args() {
+        if let Some(action) = game.bindings.lookup(button, game.modifiers) {
+            game.held.insert(button, action);
+            perform(app, action, true);
         }
     }
 
-    if let Some(Button::Mouse(button)) = e.press_args() {
-        match button {
-            MouseButton::Left => app.drawing(true),
-            MouseButton::Right => app.erasing(true),
-            MouseButton::Middle => app.moving(true),
-            _ => {}
+    if let Some(button) = e.release_args() {
+        if let Some(action) = game.held.remove(&button) {
+            perform(app, action, false);
         }
     }
 
-    if let Some(Button::Mouse(button)) = e.release_args() {
-        match button {
-            MouseButton::Left => app.drawing(false),
-            MouseButton::Right => app.erasing(false),
-            MouseButton::Middle => app.moving(false),
-            _ => {}
+    if let Some(false) = e.focus_args() {
+        for (_, action) in game.held.drain() {
+            perform(app, action, false);
         }
     }
 
@@ -95,20 +195,99 @@ fn handle_event(window: &mut Sdl2Window, e: Input, app: &mut App) {
     }
 
     if let Some(vol) = e.mouse_scroll_args() {
-        match vol[1] {
-            x if x > 0.0 => app.zoom(1),
-            x if x < 0.0 => app.zoom(-1),
-            _ => {}
-        }
+        app.zoom(vol[1] / 2.0);
+    }
+
+    if let Some(args) = e.touch_args() {
+        let touch = match args.touch {
+            piston::input::Touch::Start => Touch::Start,
+            piston::input::Touch::Move => Touch::Move,
+            piston::input::Touch::End => Touch::End,
+            piston::input::Touch::Cancel => Touch::Cancel,
+        };
+        app.touch(args.id, args.position(), touch);
     }
 
     if let Some(args) = e.render_args() {
         window.make_current();
-        app.draw(args);
+        game.screen.draw(app, args);
+    }
+}
+
+fn handle_exit(game: &mut Game) {
+    if !game.autosave {
+        return;
+    }
+    let result = Session::autosave_path()
+        .ok_or_else(|| "no config directory".to_string())
+        .and_then(|path| game.app.session().save(&path));
+    if let Err(e) = result {
+        eprintln!("failed to save session: {}", e);
+    }
+}
+
+/// Runs `action` for a press or release of its button.
+///
+/// Most actions fire once on press; scrolling, drawing and the like last while the button is held.
+fn perform(app: &mut App, action: Action, pressed: bool) {
+    if pressed {
+        match action {
+            Action::RandomInit => app.random_init(&mut rand::thread_rng()),
+            Action::Clear => app.clear(),
+            Action::ToggleRunning => app.toggle_running(),
+            Action::FitToWindow => app.fit_to_win_size(),
+            Action::Step => app.step(),
+            Action::ZoomIn => app.zoom(1.0),
+            Action::ZoomOut => app.zoom(-1.0),
+            Action::ToggleDensity => app.toggle_aggregate(),
+            Action::ToggleSmoothZoom => app.toggle_smooth_zoom(),
+            Action::ToggleHud => app.toggle_hud(),
+            Action::ToggleMinimap => app.toggle_minimap(),
+            Action::CycleEdge => app.cycle_edge(),
+            Action::ToggleOverlay => app.toggle_overlay(),
+            Action::RotateClockwise => app.transform(World::rotate_clockwise),
+            Action::RotateCounterclockwise => app.transform(World::rotate_counterclockwise),
+            Action::Rotate180 => app.transform(World::rotate_180),
+            Action::FlipHorizontal => app.transform(World::flip_horizontal),
+            Action::FlipVertical => app.transform(World::flip_vertical),
+            Action::TranslateRight => app.transform(|w| w.translate(geom::Move(1, 0))),
+            Action::TranslateLeft => app.transform(|w| w.translate(geom::Move(-1, 0))),
+            Action::TranslateUp => app.transform(|w| w.translate(geom::Move(0, -1))),
+            Action::TranslateDown => app.transform(|w| w.translate(geom::Move(0, 1))),
+            Action::Crop => app.transform(World::crop),
+            Action::GrowBoard => app.resize_board(true),
+            Action::ShrinkBoard => app.resize_board(false),
+            Action::Screenshot => app.save_screenshot(),
+            Action::SaveBoardImage => app.save_board_image(),
+            Action::SavePattern => app.save_pattern(),
+            Action::SaveSession => app.save_session(),
+            Action::LoadSession => app.load_session(),
+            Action::RecordGif => app.toggle_gif_recording(),
+            Action::RecordPng => app.toggle_png_recording(),
+            Action::ToolFreehand => app.select_tool(Tool::Freehand),
+            Action::ToolLine => app.select_tool(Tool::Line),
+            Action::ToolRectangle => app.select_tool(Tool::Rectangle { filled: false }),
+            Action::ToolFilledRectangle => app.select_tool(Tool::Rectangle { filled: true }),
+            Action::ToolEllipse => app.select_tool(Tool::Ellipse { filled: false }),
+            Action::ToolFilledEllipse => app.select_tool(Tool::Ellipse { filled: true }),
+            _ => {}
+        }
+    }
+
+    let dir = if pressed { 1 } else { -1 };
+    match action {
+        Action::ScrollRight => app.slide(geom::Move(dir, 0)),
+        Action::ScrollLeft => app.slide(geom::Move(-dir, 0)),
+        Action::ScrollUp => app.slide(geom::Move(0, -dir)),
+        Action::ScrollDown => app.slide(geom::Move(0, dir)),
+        Action::Constrain => app.constrain(pressed),
+        Action::Draw => app.drawing(pressed),
+        Action::Erase => app.erasing(pressed),
+        Action::Move => app.moving(pressed),
+        _ => {}
     }
 }
 
-#[cfg(not(target_os = "emscripten"))]
 mod event_loop {
     use piston::event_loop::{EventSettings, Events};
     use piston::input::Input;
@@ -116,84 +295,12 @@ mod event_loop {
 
     pub fn run<T>(mut window: Sdl2Window,
                   handler: fn(window: &mut Sdl2Window, e: Input, arg: &mut T),
+                  on_exit: fn(arg: &mut T),
                   mut arg: T) {
         let mut events = Events::new(EventSettings::new());
         while let Some(e) = events.next(&mut window) {
             handler(&mut window, e, &mut arg);
         }
-    }
-}
-
-#[cfg(target_os = "emscripten")]
-mod event_loop {
-    extern crate emscripten_sys;
-
-    use piston::input::{AfterRenderArgs, Input, RenderArgs, UpdateArgs};
-    use piston::window::Window;
-    use sdl2_window::Sdl2Window;
-    use std::mem;
-    use std::os::raw::c_void;
-
-    struct EventLoop<T> {
-        last_updated: f64,
-        window: Sdl2Window,
-        handler: fn(window: &mut Sdl2Window, e: Input, arg: &mut T),
-        arg: T,
-    }
-
-    pub fn run<T>(window: Sdl2Window,
-                  handler: fn(window: &mut Sdl2Window, e: Input, arg: &mut T),
-                  arg: T) {
-        unsafe {
-            let mut events = Box::new(EventLoop {
-                last_updated: emscripten_sys::emscripten_get_now() as f64,
-                window: window,
-                handler: handler,
-                arg: arg,
-            });
-            let events_ptr = &mut *events as *mut EventLoop<_> as *mut c_void;
-            emscripten_sys::emscripten_set_main_loop_arg(Some(main_loop_c::<T>), events_ptr, 0, 1);
-            mem::forget(events);
-        }
-    }
-
-    extern "C" fn main_loop_c<T>(arg: *mut c_void) {
-        unsafe {
-            let mut events: &mut EventLoop<T> = mem::transmute(arg);
-            let window = &mut events.window;
-            let handler = events.handler;
-            let arg = &mut events.arg;
-            window.swap_buffers();
-
-            let e = Input::AfterRender(AfterRenderArgs);
-            handler(window, e, arg);
-
-            while let Some(e) = window.poll_event() {
-                handler(window, e, arg);
-            }
-
-            if window.should_close() {
-                emscripten_sys::emscripten_cancel_main_loop();
-                return;
-            }
-
-            let now = emscripten_sys::emscripten_get_now() as f64;
-            let dt = now - events.last_updated;
-            events.last_updated = now;
-
-            let e = Input::Update(UpdateArgs { dt: dt });
-            handler(window, e, arg);
-
-            let size = window.size();
-            let draw_size = window.draw_size();
-            let e = Input::Render(RenderArgs {
-                ext_dt: dt,
-                width: size.width,
-                height: size.height,
-                draw_width: draw_size.width,
-                draw_height: draw_size.height,
-            });
-            handler(window, e, arg);
-        }
+        on_exit(&mut arg);
     }
 }
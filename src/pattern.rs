@@ -0,0 +1,368 @@
+use geom::{Point, Size};
+use std::cmp;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::iter;
+use std::path::Path;
+use world::{RuleSpec, World};
+
+const RLE_LINE_WIDTH: usize = 70;
+
+/// A pattern of cells, independent of any board.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    size: Size,
+    cells: Vec<(Point, u8)>,
+    rule: Option<RuleSpec>,
+}
+
+impl Pattern {
+    pub fn load(path: &Path) -> Result<Pattern, String> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Pattern::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        File::create(path)
+            .and_then(|mut f| f.write_all(self.to_rle().as_bytes()))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Takes all non-dead cells of the world, together with its rule.
+    pub fn from_world(world: &World) -> Pattern {
+        let size = world.size();
+        let mut cells = vec![];
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let state = world.state(Point(x, y));
+                if state != 0 {
+                    cells.push((Point(x, y), state));
+                }
+            }
+        }
+        Pattern {
+            size: size,
+            cells: cells,
+            rule: Some(world.rule()),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn rule(&self) -> Option<&RuleSpec> {
+        self.rule.as_ref()
+    }
+
+    /// Copies the cells onto `world` with the top-left corner of the pattern at `origin`.
+    /// Cells outside of the world are dropped.
+    pub fn draw(&self, world: &mut World, origin: Point) {
+        for &(p, state) in &self.cells {
+            let p = Point(origin.0 + p.0, origin.1 + p.1);
+            if world.contains(p) {
+                world.set_state(p, state);
+            }
+        }
+    }
+
+    /// Parses a pattern in RLE, plaintext or Life 1.06 format, telling them apart by the
+    /// content.
+    pub fn parse(s: &str) -> Result<Pattern, String> {
+        if s.trim().starts_with("#Life 1.06") {
+            return Pattern::parse_life106(s);
+        }
+        let plaintext = s.lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('!'))
+            .all(|line| !line.starts_with('#') && line.chars().all(|c| ".O*".contains(c)));
+        if plaintext && !s.trim().is_empty() {
+            Pattern::parse_plaintext(s)
+        } else {
+            Pattern::parse_rle(s)
+        }
+    }
+
+    /// Parses a pattern in plaintext format, with `!` comment lines and `.` and `O` (or `*`)
+    /// for dead and live cells.
+    pub fn parse_plaintext(s: &str) -> Result<Pattern, String> {
+        let mut cells = vec![];
+        let mut size = Size(0, 0);
+        for line in s.lines().map(str::trim).filter(|line| !line.starts_with('!')) {
+            for (x, c) in line.chars().enumerate() {
+                match c {
+                    '.' => {}
+                    'O' | '*' => cells.push((Point(x as i32, size.1), 1)),
+                    c => return Err(format!("unexpected `{}` in plaintext pattern", c)),
+                }
+            }
+            size = Size(cmp::max(size.0, line.chars().count() as i32), size.1 + 1);
+        }
+        Ok(Pattern {
+            size: size,
+            cells: cells,
+            rule: None,
+        })
+    }
+
+    /// Parses a pattern in Life 1.06 format, which lists the coordinates of live cells.
+    pub fn parse_life106(s: &str) -> Result<Pattern, String> {
+        let mut points = vec![];
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let mut it = line.split_whitespace().map(str::parse::<i32>);
+            match (it.next(), it.next(), it.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => points.push(Point(x, y)),
+                _ => return Err(format!("invalid Life 1.06 line: {}", line)),
+            }
+        }
+        if points.is_empty() {
+            return Ok(Pattern {
+                size: Size(0, 0),
+                cells: vec![],
+                rule: None,
+            });
+        }
+        let min = points.iter()
+            .fold(points[0], |m, p| Point(cmp::min(m.0, p.0), cmp::min(m.1, p.1)));
+        let max = points.iter()
+            .fold(points[0], |m, p| Point(cmp::max(m.0, p.0), cmp::max(m.1, p.1)));
+        Ok(Pattern {
+            size: Size(max.0 - min.0 + 1, max.1 - min.1 + 1),
+            cells: points.into_iter().map(|p| (Point(p.0 - min.0, p.1 - min.1), 1)).collect(),
+            rule: None,
+        })
+    }
+
+    /// Parses a pattern in RLE format.
+    ///
+    /// Both the two-state (`b`, `o`) and the multi-state (`.`, `A`..`X`, `pA`..`yO`) cell tags
+    /// are accepted.
+    pub fn parse_rle(s: &str) -> Result<Pattern, String> {
+        let mut lines = s.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let mut size = None;
+        let mut rule = None;
+        let header = lines.next().ok_or_else(|| "empty pattern".to_string())?;
+        let mut body = String::new();
+        if header.starts_with('x') {
+            let mut fields = header.split(',');
+            while let Some(field) = fields.next() {
+                let mut it = field.splitn(2, '=').map(str::trim);
+                match (it.next(), it.next()) {
+                    (Some("x"), Some(v)) => {
+                        size = Some(Size(parse_dim(v)?, size.map_or(0, |s: Size| s.1)))
+                    }
+                    (Some("y"), Some(v)) => {
+                        size = Some(Size(size.map_or(0, |s: Size| s.0), parse_dim(v)?))
+                    }
+                    // The rule comes last and may contain commas itself.
+                    (Some("rule"), Some(v)) => {
+                        let v = iter::once(v).chain(fields.by_ref()).collect::<Vec<_>>().join(",");
+                        rule = Some(v.parse()?);
+                    }
+                    _ => return Err(format!("invalid RLE header: {}", header)),
+                }
+            }
+        } else {
+            body.push_str(header);
+        }
+        for line in lines {
+            body.push_str(line);
+        }
+
+        let mut cells = vec![];
+        let mut pos = Point(0, 0);
+        let mut end = Point(0, 0);
+        let mut count = None;
+        let mut prefix = None;
+        for c in body.chars() {
+            if let Some(d) = c.to_digit(10) {
+                if prefix.is_some() {
+                    return Err(format!("unexpected `{}` after state prefix", c));
+                }
+                count = Some(count.unwrap_or(0) * 10 + d as i32);
+                continue;
+            }
+            let n = count.take().unwrap_or(1);
+            let state = match c {
+                '!' | '$' | 'b' | '.' if prefix.is_some() => {
+                    return Err("expected A-X after state prefix".to_string())
+                }
+                '!' => break,
+                '$' => {
+                    pos = Point(0, pos.1 + n);
+                    continue;
+                }
+                'b' | '.' => 0,
+                c if 'p' <= c && c <= 'y' && prefix.is_none() => {
+                    prefix = Some((c as u32) - ('p' as u32) + 1);
+                    count = if n == 1 { None } else { Some(n) };
+                    continue;
+                }
+                c if 'A' <= c && c <= 'X' => {
+                    prefix.take().unwrap_or(0) * 24 + (c as u32) - ('A' as u32) + 1
+                }
+                c if c.is_ascii_lowercase() && prefix.is_none() => 1,
+                c if c.is_whitespace() => continue,
+                c => return Err(format!("unexpected `{}` in RLE", c)),
+            };
+            if state > 255 {
+                return Err(format!("state {} is out of range", state));
+            }
+            if state != 0 {
+                for i in 0..n {
+                    cells.push((Point(pos.0 + i, pos.1), state as u8));
+                }
+            }
+            pos.0 += n;
+            end = Point(cmp::max(end.0, pos.0), cmp::max(end.1, pos.1 + 1));
+        }
+        if prefix.is_some() {
+            return Err("expected A-X after state prefix".to_string());
+        }
+
+        let size = match size {
+            Some(size) => Size(cmp::max(size.0, end.0), cmp::max(size.1, end.1)),
+            None => Size(end.0, end.1),
+        };
+        Ok(Pattern {
+            size: size,
+            cells: cells,
+            rule: rule,
+        })
+    }
+
+    /// Formats the pattern in RLE format, using the multi-state cell tags if needed.
+    pub fn to_rle(&self) -> String {
+        let multi_state = self.rule.as_ref().map_or(false, |r| r.states() > 2) ||
+                          self.cells.iter().any(|&(_, s)| s > 1);
+
+        let mut out = format!("x = {}, y = {}", self.size.0, self.size.1);
+        if let Some(ref rule) = self.rule {
+            out.push_str(&format!(", rule = {}", rule));
+        }
+        out.push('\n');
+
+        let mut cells = self.cells.clone();
+        cells.sort_by_key(|&(p, _)| (p.1, p.0));
+
+        // Runs of `(count, tag)`, with `$` for line ends.
+        let mut runs = vec![];
+        let mut pos = Point(0, 0);
+        for (p, state) in cells {
+            if p.1 > pos.1 {
+                push_run(&mut runs, p.1 - pos.1, "$".to_string());
+                pos = Point(0, p.1);
+            }
+            let dead = if multi_state { "." } else { "b" };
+            push_run(&mut runs, p.0 - pos.0, dead.to_string());
+            push_run(&mut runs, 1, state_tag(state, multi_state));
+            pos.0 = p.0 + 1;
+        }
+
+        let mut line = String::new();
+        for item in runs.into_iter()
+            .map(|(n, tag)| if n == 1 { tag } else { format!("{}{}", n, tag) })
+            .chain(Some("!".to_string())) {
+            if line.len() + item.len() > RLE_LINE_WIDTH {
+                out.push_str(&line);
+                out.push('\n');
+                line.clear();
+            }
+            line.push_str(&item);
+        }
+        out.push_str(&line);
+        out.push('\n');
+        out
+    }
+}
+
+fn push_run(runs: &mut Vec<(i32, String)>, n: i32, tag: String) {
+    if n <= 0 {
+        return;
+    }
+    if let Some(last) = runs.last_mut() {
+        if last.1 == tag {
+            last.0 += n;
+            return;
+        }
+    }
+    runs.push((n, tag));
+}
+
+fn state_tag(state: u8, multi_state: bool) -> String {
+    if !multi_state {
+        return "o".to_string();
+    }
+    let n = (state - 1) as u32;
+    let letter = ((b'A' as u32) + n % 24) as u8 as char;
+    if n < 24 {
+        letter.to_string()
+    } else {
+        format!("{}{}", ((b'p' as u32) + n / 24 - 1) as u8 as char, letter)
+    }
+}
+
+fn parse_dim(s: &str) -> Result<i32, String> {
+    match s.parse() {
+        Ok(n) if n >= 0 => Ok(n),
+        _ => Err(format!("invalid pattern size: {}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_cells(pattern: &Pattern) -> Vec<(Point, u8)> {
+        let mut cells = pattern.cells.clone();
+        cells.sort_by_key(|&(p, _)| (p.1, p.0));
+        cells
+    }
+
+    #[test]
+    fn parse_rle() {
+        let pattern = Pattern::parse_rle("x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!").unwrap();
+        assert_eq!(pattern.size(), Size(3, 3));
+        assert_eq!(pattern.rule().unwrap().to_string(), "B3/S23");
+        assert_eq!(sorted_cells(&pattern),
+                   vec![(Point(1, 0), 1), (Point(2, 1), 1), (Point(0, 2), 1), (Point(1, 2), 1),
+                        (Point(2, 2), 1)]);
+    }
+
+    #[test]
+    fn parse_multi_state_rle() {
+        let pattern = Pattern::parse_rle("x = 4, y = 2, rule = B2/S/C30\n.AB$pA2xX!").unwrap();
+        assert_eq!(sorted_cells(&pattern),
+                   vec![(Point(1, 0), 1), (Point(2, 0), 2), (Point(0, 1), 25),
+                        (Point(1, 1), 240), (Point(2, 1), 240)]);
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        for rle in &["x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!",
+                     "x = 5, y = 3, rule = B2/S/C30\n.AB$$pA2.xX!",
+                     "x = 8, y = 1\n8o!"] {
+            let pattern = Pattern::parse_rle(rle).unwrap();
+            let parsed = Pattern::parse_rle(&pattern.to_rle()).unwrap();
+            assert_eq!(parsed.size(), pattern.size());
+            assert_eq!(parsed.rule().map(|r| r.to_string()),
+                       pattern.rule().map(|r| r.to_string()));
+            assert_eq!(sorted_cells(&parsed), sorted_cells(&pattern));
+        }
+    }
+
+    #[test]
+    fn reject_malformed_rle() {
+        for rle in &["x = 3, y = z\n3o!", "x = 1, y = 1\n3o?!", "x = 2, y = 1\npb!",
+                     "x = 3, y = 1\npb2A!", "x = 1, y = 2\np$A!", "x = 1, y = 1\np!",
+                     "x = 1, y = 1\np2A!", "x = 1, y = 1\nyX!"] {
+            assert!(Pattern::parse_rle(rle).is_err(), "{}", rle);
+        }
+    }
+}
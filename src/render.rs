@@ -0,0 +1,134 @@
+use geom::{Move, Point, Size};
+use im::{ImageBuffer, Rgba};
+use std::cmp;
+use world::World;
+
+pub type Canvas = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+/// Colors of cells alive only on the board and only on the overlaid board.
+const ONLY_BOARD_COLOR: [u8; 4] = [64, 224, 64, 255];
+const ONLY_OVERLAY_COLOR: [u8; 4] = [224, 64, 224, 255];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Aggregate {
+    AnyAlive,
+    Density,
+}
+
+/// Draws `world` onto `canvas` with its top-left cell at `offset` and cells `scale` pixels wide.
+///
+/// Odd rows of hexagonal boards are drawn shifted right by half a cell, as long as cells are
+/// at least a pixel wide.
+pub fn render_cells(canvas: &mut Canvas,
+                    world: &World,
+                    offset: Move,
+                    scale: f64,
+                    aggregate: Aggregate) {
+    for_each_pixel(canvas, world, offset, scale, |p, size| {
+        Some(pixel_color(world, aggregate, p, size))
+    });
+}
+
+/// Colors the pixels covering cells alive on only one of two overlaid boards, given as the
+/// differences `only_board` and `only_overlay`, over what `render_cells` drew.
+pub fn render_differences(canvas: &mut Canvas,
+                          only_board: &World,
+                          only_overlay: &World,
+                          offset: Move,
+                          scale: f64) {
+    for_each_pixel(canvas, only_board, offset, scale, |p, size| {
+        if only_board.count_in_rect(p, size) > 0 {
+            Some(ONLY_BOARD_COLOR)
+        } else if only_overlay.count_in_rect(p, size) > 0 {
+            Some(ONLY_OVERLAY_COLOR)
+        } else {
+            None
+        }
+    });
+}
+
+/// Calls `f` with the cells covered by each pixel of `canvas`, and paints the pixel with the
+/// color it returns.
+fn for_each_pixel<F>(canvas: &mut Canvas, world: &World, offset: Move, scale: f64, mut f: F)
+    where F: FnMut(Point, Size) -> Option<[u8; 4]>
+{
+    let (width, height) = canvas.dimensions();
+    let xs = (0..width as i32)
+        .map(|wx| pixel_range(wx - offset.0, scale))
+        .collect::<Vec<_>>();
+    let ys = (0..height as i32)
+        .map(|wy| pixel_range(wy - offset.1, scale))
+        .collect::<Vec<_>>();
+    let shift = odd_row_shift(world, scale);
+    let odd_xs = (0..width as i32)
+        .map(|wx| pixel_range(wx - offset.0 - shift, scale))
+        .collect::<Vec<_>>();
+
+    for (wy, &(y, h)) in ys.iter().enumerate() {
+        let xs = if h == 1 && y % 2 != 0 { &odd_xs } else { &xs };
+        for (wx, &(x, w)) in xs.iter().enumerate() {
+            if let Some(color) = f(Point(x, y), Size(w, h)) {
+                canvas.put_pixel(wx as u32, wy as u32, Rgba(color));
+            }
+        }
+    }
+}
+
+/// Renders the whole world into a new canvas.
+pub fn render_board(world: &World, scale: f64, aggregate: Aggregate) -> Canvas {
+    let size = world.size();
+    let width = cmp::max(((size.0 as f64) * scale).ceil() as u32, 1);
+    let height = cmp::max(((size.1 as f64) * scale).ceil() as u32, 1);
+    let mut canvas = ImageBuffer::new(width, height);
+    render_cells(&mut canvas, world, Move(0, 0), scale, aggregate);
+    canvas
+}
+
+/// Returns how many pixels odd rows are shifted to the right.
+pub fn odd_row_shift(world: &World, scale: f64) -> i32 {
+    if world.is_hexagonal() && scale >= 1.0 {
+        (scale / 2.0).floor() as i32
+    } else {
+        0
+    }
+}
+
+/// Returns the first cell and the number of cells covered by the pixel at `n`.
+pub fn pixel_range(n: i32, scale: f64) -> (i32, i32) {
+    let start = ((n as f64) / scale).floor() as i32;
+    let end = (((n + 1) as f64) / scale).floor() as i32;
+    (start, cmp::max(end - start, 1))
+}
+
+/// Returns the color of a pixel covering the cells in the rectangle.
+pub fn pixel_color(world: &World, aggregate: Aggregate, p: Point, size: Size) -> [u8; 4] {
+    if size == Size(1, 1) {
+        return if !world.contains(p) {
+            [128, 128, 128, 255]
+        } else {
+            world.color(world.state(p))
+        };
+    }
+
+    let board_size = world.size();
+    let w = cmp::min(p.0 + size.0, board_size.0) - cmp::max(p.0, 0);
+    let h = cmp::min(p.1 + size.1, board_size.1) - cmp::max(p.1, 0);
+    if w <= 0 || h <= 0 {
+        return [128, 128, 128, 255];
+    }
+
+    let count = world.count_in_rect(p, size);
+    match aggregate {
+        Aggregate::AnyAlive => {
+            if count > 0 {
+                [255, 255, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        }
+        Aggregate::Density => {
+            let v = (255 * count / ((w * h) as u32)) as u8;
+            [v, v, v, 255]
+        }
+    }
+}
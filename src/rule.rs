@@ -0,0 +1,619 @@
+use grid::BoundedGrid;
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+/// A totalistic rule, optionally with decaying states.
+///
+/// `states == 2` is an ordinary two-state rule. With more states it is a "Generations" rule:
+/// a live cell that does not survive becomes state 2 and then ages one state per generation
+/// until it reaches `states - 1` and dies. Only live (state 1) cells count as neighbours, and
+/// only empty (state 0) cells can be born.
+///
+/// Rules with `range == 1` are the Life-like rules, on the Moore (`B3/S23`), von Neumann
+/// (`B1/S1V`) or hexagonal (`B2/S34H`) neighbourhood; larger ranges are Larger than Life rules
+/// (`R5,C0,M1,S34..58,B34..45,NM`). Life-like rules on the Moore neighbourhood may also be
+/// isotropic non-totalistic (`B2-a/S12`), depending on the arrangement of the neighbours and not
+/// only on their count.
+///
+/// Any of these may end with a bounded grid such as `B3/S23:T100,80`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rule {
+    range: u32,
+    neighbourhood: Neighbourhood,
+    middle: bool,
+    birth: Vec<bool>,
+    survival: Vec<bool>,
+    // For non-totalistic rules, whether the centre of each 3x3 configuration is alive in the
+    // next generation. The cells are numbered in raster order with the centre at bit 4.
+    table: Option<Vec<bool>>,
+    states: u32,
+    grid: Option<BoundedGrid>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Neighbourhood {
+    Moore,
+    VonNeumann,
+    /// The six neighbours on a hexagonal lattice stored with odd rows shifted half a cell to
+    /// the right: W, E and two cells each in the rows above and below.
+    Hexagonal,
+}
+
+pub const MAX_STATES: u32 = 256;
+pub const MAX_RANGE: u32 = 50;
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::new(&[3], &[2, 3], 2).unwrap()
+    }
+}
+
+impl Neighbourhood {
+    /// Returns the number of cells within `range` of a cell, excluding the cell itself.
+    pub fn size(&self, range: u32) -> u32 {
+        match *self {
+            Neighbourhood::Moore => (2 * range + 1) * (2 * range + 1) - 1,
+            Neighbourhood::VonNeumann => 2 * range * (range + 1),
+            Neighbourhood::Hexagonal => 3 * range * (range + 1),
+        }
+    }
+}
+
+impl Rule {
+    /// Creates a Life-like rule.
+    pub fn new(birth: &[u32], survival: &[u32], states: u32) -> Result<Rule, String> {
+        let birth = birth.iter().map(|&n| (n, n)).collect::<Vec<_>>();
+        let survival = survival.iter().map(|&n| (n, n)).collect::<Vec<_>>();
+        Rule::with_range(1, Neighbourhood::Moore, false, &birth, &survival, states)
+    }
+
+    /// Creates a Larger than Life rule. `birth` and `survival` are inclusive ranges of counts.
+    /// If `middle` is set, a live cell counts itself as a neighbour.
+    pub fn with_range(range: u32,
+                      neighbourhood: Neighbourhood,
+                      middle: bool,
+                      birth: &[(u32, u32)],
+                      survival: &[(u32, u32)],
+                      states: u32)
+                      -> Result<Rule, String> {
+        if range < 1 || range > MAX_RANGE {
+            return Err(format!("range must be between 1 and {}", MAX_RANGE));
+        }
+        if neighbourhood == Neighbourhood::Hexagonal && (range > 1 || middle) {
+            return Err("the hexagonal neighbourhood only supports range 1 rules".to_string());
+        }
+        let max = neighbourhood.size(range) + if middle { 1 } else { 0 };
+        let counts = |ranges: &[(u32, u32)]| {
+            let mut counts = vec![false; (max + 1) as usize];
+            for &(min, end) in ranges {
+                if min > end || end > max {
+                    return Err(format!("neighbour count {}..{} out of range 0..{}", min, end, max));
+                }
+                for n in min..(end + 1) {
+                    counts[n as usize] = true;
+                }
+            }
+            Ok(counts)
+        };
+
+        let rule = Rule {
+            range: range,
+            neighbourhood: neighbourhood,
+            middle: middle,
+            birth: counts(birth)?,
+            survival: counts(survival)?,
+            table: None,
+            states: states,
+            grid: None,
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Returns true if a dead cell with `n` live neighbours is born.
+    pub fn is_birth(&self, n: u32) -> bool {
+        self.birth.get(n as usize).cloned().unwrap_or(false)
+    }
+
+    /// Returns true if a live cell with `n` live neighbours survives.
+    pub fn is_survival(&self, n: u32) -> bool {
+        self.survival.get(n as usize).cloned().unwrap_or(false)
+    }
+
+    /// Returns the birth counts of a Life-like rule as a bit set.
+    pub fn birth_mask(&self) -> u16 {
+        (0..9).filter(|&n| self.is_birth(n)).fold(0, |acc, n| acc | (1 << n))
+    }
+
+    /// Returns the survival counts of a Life-like rule as a bit set.
+    pub fn survival_mask(&self) -> u16 {
+        (0..9).filter(|&n| self.is_survival(n)).fold(0, |acc, n| acc | (1 << n))
+    }
+
+    /// Creates an isotropic non-totalistic rule from the Hensel classes of each neighbour count,
+    /// given as bit sets indexed like the letters in `HENSEL_LETTERS`.
+    ///
+    /// Falls back to a totalistic rule if every count is either fully included or excluded.
+    pub fn isotropic(birth: &[u16; 9], survival: &[u16; 9], states: u32) -> Result<Rule, String> {
+        let full = |n: usize| (1 << hensel_classes(n)) - 1;
+        let totalistic = |classes: &[u16; 9]| {
+            (0..9).all(|n| classes[n] == 0 || classes[n] == full(n))
+        };
+        let counts = |classes: &[u16; 9]| {
+            (0..9).filter(|&n| classes[n] != 0).map(|n| n as u32).collect::<Vec<_>>()
+        };
+        if totalistic(birth) && totalistic(survival) {
+            return Rule::new(&counts(birth), &counts(survival), states);
+        }
+
+        let mut rule = Rule::new(&[], &[], states)?;
+        let table = (0..512)
+            .map(|config| {
+                let (n, class) = hensel_class(config);
+                let classes = if (config & CENTRE) != 0 { survival } else { birth };
+                (classes[n] & (1 << class)) != 0
+            })
+            .collect();
+        rule.table = Some(table);
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Returns whether the centre of each 3x3 configuration is alive in the next generation,
+    /// for non-totalistic rules. The cells are numbered in raster order from the top-left
+    /// corner, with the centre at bit 4.
+    pub fn table(&self) -> Option<&[bool]> {
+        self.table.as_ref().map(|t| &t[..])
+    }
+
+    /// Returns the number of cell states, including the dead state.
+    pub fn states(&self) -> u32 {
+        self.states
+    }
+
+    pub fn range(&self) -> u32 {
+        self.range
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    /// Returns the bounded grid, which fixes the board size and how the edges are joined.
+    pub fn grid(&self) -> Option<BoundedGrid> {
+        self.grid
+    }
+
+    /// Returns true if a live cell counts itself as a neighbour.
+    pub fn middle(&self) -> bool {
+        self.middle
+    }
+
+    /// Returns true if the rule only looks at the adjacent cells, excluding the cell itself.
+    pub fn is_life_like(&self) -> bool {
+        self.range == 1 && !self.middle
+    }
+
+    pub fn is_conway(&self) -> bool {
+        Rule { grid: None, ..self.clone() } == Rule::default()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.is_birth(0) || self.table.as_ref().map_or(false, |t| t[0]) {
+            return Err("rules with B0 are not supported".to_string());
+        }
+        if self.states < 2 || self.states > MAX_STATES {
+            return Err(format!("number of states must be between 2 and {}", MAX_STATES));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the centre of a 3x3 configuration is alive in the next generation.
+    fn next_state(&self, config: u16) -> bool {
+        if let Some(ref table) = self.table {
+            return table[config as usize];
+        }
+        let n = (config & !CENTRE).count_ones();
+        if (config & CENTRE) != 0 {
+            self.is_survival(n)
+        } else {
+            self.is_birth(n)
+        }
+    }
+
+    /// Writes the rule without the bounded grid.
+    fn fmt_transitions(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.is_life_like() {
+            let neighbourhood = match self.neighbourhood {
+                Neighbourhood::Moore => "M",
+                Neighbourhood::VonNeumann => "N",
+                Neighbourhood::Hexagonal => "H",
+            };
+            let states = if self.states > 2 { self.states } else { 0 };
+            write!(f, "R{},C{},M{}", self.range, states, if self.middle { 1 } else { 0 })?;
+            for (min, max) in count_ranges(&self.survival) {
+                write!(f, ",S{}..{}", min, max)?;
+            }
+            for (min, max) in count_ranges(&self.birth) {
+                write!(f, ",B{}..{}", min, max)?;
+            }
+            return write!(f, ",N{}", neighbourhood);
+        }
+
+        write!(f, "B")?;
+        for n in 0..9 {
+            write_hensel(f, n, |config| self.next_state(config))?;
+        }
+        write!(f, "/S")?;
+        for n in 0..9 {
+            write_hensel(f, n, |config| self.next_state(config | CENTRE))?;
+        }
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        match self.neighbourhood {
+            Neighbourhood::Moore => Ok(()),
+            Neighbourhood::VonNeumann => write!(f, "V"),
+            Neighbourhood::Hexagonal => write!(f, "H"),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    /// Parses `B3/S23`, `B2/S/C3` (or `B2/S/3`), isotropic non-totalistic rules such as
+    /// `B2-a/S12` or `B2ce3aiy/S23-k`, the older `S/B` and `S/B/C` forms such as
+    /// `23/3` and `/2/3`, and Larger than Life rules such as `R5,C0,M1,S34..58,B34..45,NM`.
+    /// Life-like rules take an `H` or `V` suffix for the hexagonal or von Neumann neighbourhood,
+    /// and any rule may be followed by `:` and a bounded grid.
+    fn from_str(s: &str) -> Result<Rule, String> {
+        let mut parts = s.trim().splitn(2, ':');
+        let mut rule = parse_rule(parts.next().unwrap_or("").trim())?;
+        if let Some(grid) = parts.next() {
+            rule.grid = Some(grid.parse()?);
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_transitions(f)?;
+        match self.grid {
+            Some(grid) => write!(f, ":{}", grid),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses a rule without the bounded grid.
+fn parse_rule(s: &str) -> Result<Rule, String> {
+    if s.starts_with(|c| c == 'R' || c == 'r') && s.contains(',') {
+        return parse_larger_than_life(s);
+    }
+
+    let (s, neighbourhood) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('H') => (&s[..(s.len() - 1)], Neighbourhood::Hexagonal),
+        Some('V') => (&s[..(s.len() - 1)], Neighbourhood::VonNeumann),
+        _ => (s, Neighbourhood::Moore),
+    };
+
+    let parts = s.split('/').map(str::trim).collect::<Vec<_>>();
+    let invalid = || format!("invalid rule: {}", s);
+
+    let mut birth = None;
+    let mut survival = None;
+    let mut states = None;
+
+    if parts.iter().any(|p| p.starts_with(|c: char| c.is_alphabetic())) {
+        for part in &parts {
+            let mut chars = part.chars();
+            match chars.next().map(|c| c.to_ascii_uppercase()) {
+                Some('B') if birth.is_none() => birth = Some(parse_hensel(chars.as_str())?),
+                Some('S') if survival.is_none() => {
+                    survival = Some(parse_hensel(chars.as_str())?)
+                }
+                Some('C') | Some('G') if states.is_none() => {
+                    states = Some(chars.as_str().parse().map_err(|_| invalid())?)
+                }
+                Some(c) if c.is_digit(10) && states.is_none() => {
+                    states = Some(part.parse().map_err(|_| invalid())?)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+    } else {
+        match parts.len() {
+            2 | 3 => {
+                survival = Some(parse_hensel(parts[0])?);
+                birth = Some(parse_hensel(parts[1])?);
+                if parts.len() == 3 {
+                    states = Some(parts[2].parse().map_err(|_| invalid())?);
+                }
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    let (birth, survival) = match (birth, survival) {
+        (Some(birth), Some(survival)) => (birth, survival),
+        _ => return Err(invalid()),
+    };
+    let rule = Rule::isotropic(&birth, &survival, states.unwrap_or(2))?;
+    if neighbourhood == Neighbourhood::Moore {
+        return Ok(rule);
+    }
+    if rule.table.is_some() {
+        return Err("non-totalistic rules need the Moore neighbourhood".to_string());
+    }
+    let counts = |set: &[bool]| {
+        (0..set.len()).filter(|&n| set[n]).map(|n| (n as u32, n as u32)).collect::<Vec<_>>()
+    };
+    Rule::with_range(1,
+                     neighbourhood,
+                     false,
+                     &counts(&rule.birth),
+                     &counts(&rule.survival),
+                     rule.states)
+}
+
+
+/// Parses the `R5,C0,M1,S34..58,B34..45,NM` form. `S` and `B` may be repeated, and a single
+/// count may be given instead of a range.
+fn parse_larger_than_life(s: &str) -> Result<Rule, String> {
+    let invalid = || format!("invalid rule: {}", s);
+
+    let mut range = None;
+    let mut states = 2;
+    let mut middle = false;
+    let mut birth = vec![];
+    let mut survival = vec![];
+    let mut neighbourhood = Neighbourhood::Moore;
+
+    for part in s.split(',').map(str::trim) {
+        let mut chars = part.chars();
+        let key = chars.next().map(|c| c.to_ascii_uppercase());
+        let value = chars.as_str();
+        match key {
+            Some('R') => range = Some(value.parse().map_err(|_| invalid())?),
+            Some('C') => states = ltl_states(value.parse().map_err(|_| invalid())?),
+            Some('M') => {
+                middle = match value {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err(invalid()),
+                }
+            }
+            Some('S') => survival.push(parse_count_range(value).ok_or_else(&invalid)?),
+            Some('B') => birth.push(parse_count_range(value).ok_or_else(&invalid)?),
+            Some('N') => {
+                neighbourhood = match value {
+                    "M" | "m" => Neighbourhood::Moore,
+                    "N" | "n" => Neighbourhood::VonNeumann,
+                    _ => return Err(format!("unknown neighbourhood `{}`", value)),
+                }
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    let range = range.ok_or_else(&invalid)?;
+    Rule::with_range(range, neighbourhood, middle, &birth, &survival, states)
+}
+
+/// `C0` and `C1` both mean two states.
+fn ltl_states(states: u32) -> u32 {
+    if states < 2 { 2 } else { states }
+}
+
+fn parse_count_range(s: &str) -> Option<(u32, u32)> {
+    let mut it = s.splitn(2, "..");
+    match (it.next().map(str::parse), it.next().map(str::parse)) {
+        (Some(Ok(min)), Some(Ok(max))) => Some((min, max)),
+        (Some(Ok(n)), None) => Some((n, n)),
+        _ => None,
+    }
+}
+
+/// Returns the runs of set counts as inclusive ranges.
+fn count_ranges(counts: &[bool]) -> Vec<(u32, u32)> {
+    let mut ranges = vec![];
+    let mut start = None;
+    for (n, &set) in counts.iter().chain(Some(&false)).enumerate() {
+        match (set, start) {
+            (true, None) => start = Some(n as u32),
+            (false, Some(min)) => {
+                ranges.push((min, n as u32 - 1));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// Bit of the centre cell in a 3x3 configuration.
+const CENTRE: u16 = 1 << 4;
+
+/// Hensel letters of the configurations with 0 to 4 live neighbours. Those with `n > 4` are
+/// named after the complement with `8 - n` live neighbours.
+const HENSEL_LETTERS: [&'static str; 5] = ["", "ce", "ceaikn", "ceaiknjqry", "ceaiknjqrytwz"];
+
+/// A configuration of each Hensel class, in the same order as `HENSEL_LETTERS`.
+const HENSEL_CONFIGS: [&'static [u16]; 5] =
+    [&[0],
+     &[1, 2],
+     &[5, 10, 3, 40, 33, 68],
+     &[69, 42, 11, 7, 98, 13, 14, 70, 41, 97],
+     &[325, 170, 15, 45, 99, 71, 106, 102, 43, 101, 105, 78, 108]];
+
+const NEIGHBOURS: u16 = 0x1ff & !CENTRE;
+
+fn hensel_letters(n: usize) -> &'static str {
+    HENSEL_LETTERS[cmp::min(n, 8 - n)]
+}
+
+/// Returns the number of classes with `n` live neighbours.
+fn hensel_classes(n: usize) -> usize {
+    cmp::max(hensel_letters(n).len(), 1)
+}
+
+/// Returns the neighbour count and the class of a 3x3 configuration, ignoring the centre.
+fn hensel_class(config: u16) -> (usize, usize) {
+    let config = config & NEIGHBOURS;
+    let n = config.count_ones() as usize;
+    for class in 0..hensel_classes(n) {
+        if symmetries(hensel_config(n, class)).contains(&config) {
+            return (n, class);
+        }
+    }
+    unreachable!()
+}
+
+/// Returns a configuration with `n` live neighbours in the given Hensel class.
+fn hensel_config(n: usize, class: usize) -> u16 {
+    if n <= 4 {
+        HENSEL_CONFIGS[n][class]
+    } else {
+        !HENSEL_CONFIGS[8 - n][class] & NEIGHBOURS
+    }
+}
+
+/// Returns the configuration under the 8 rotations and reflections of the square.
+fn symmetries(config: u16) -> Vec<u16> {
+    let transforms: [fn(usize, usize) -> (usize, usize); 8] = [|x, y| (x, y),
+                                                                |x, y| (2 - y, x),
+                                                                |x, y| (2 - x, 2 - y),
+                                                                |x, y| (y, 2 - x),
+                                                                |x, y| (2 - x, y),
+                                                                |x, y| (x, 2 - y),
+                                                                |x, y| (y, x),
+                                                                |x, y| (2 - y, 2 - x)];
+    transforms.iter()
+        .map(|transform| {
+            (0..9)
+                .filter(|&i| (config & (1 << i)) != 0)
+                .map(|i| transform(i % 3, i / 3))
+                .fold(0, |acc, (x, y)| acc | (1 << (y * 3 + x)))
+        })
+        .collect()
+}
+
+/// Parses the neighbour conditions of `B` or `S`, such as `2-a3` or `2ce3aiy`, into bit sets of
+/// Hensel classes for each count.
+fn parse_hensel(s: &str) -> Result<[u16; 9], String> {
+    let mut classes = [0; 9];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let n = match c.to_digit(10) {
+            Some(n) if n <= 8 => n as usize,
+            _ => return Err(format!("invalid neighbour count `{}`", c)),
+        };
+        let negate = chars.peek() == Some(&'-');
+        if negate {
+            chars.next();
+        }
+        let mut letters = 0;
+        while let Some(&c) = chars.peek() {
+            if !c.is_alphabetic() {
+                break;
+            }
+            chars.next();
+            match hensel_letters(n).find(c.to_ascii_lowercase()) {
+                Some(class) => letters |= 1 << class,
+                None => return Err(format!("invalid neighbourhood `{}{}`", n, c)),
+            }
+        }
+        let full = (1 << hensel_classes(n)) - 1;
+        classes[n] |= match (negate, letters) {
+            (_, 0) => full,
+            (true, letters) => full & !letters,
+            (false, letters) => letters,
+        };
+    }
+    Ok(classes)
+}
+
+/// Writes the Hensel classes with `n` live neighbours that `included` accepts, using the
+/// shorter of the `2ce` and `2-aikn` forms.
+fn write_hensel<F>(f: &mut fmt::Formatter, n: usize, included: F) -> fmt::Result
+    where F: Fn(u16) -> bool
+{
+    let letters = hensel_letters(n);
+    let set = (0..hensel_classes(n))
+        .filter(|&class| included(hensel_config(n, class)))
+        .collect::<Vec<_>>();
+    if set.is_empty() {
+        return Ok(());
+    }
+    write!(f, "{}", n)?;
+    if set.len() == hensel_classes(n) {
+        return Ok(());
+    }
+    let negate = set.len() * 2 > letters.len();
+    if negate {
+        write!(f, "-")?;
+    }
+    for (class, c) in letters.chars().enumerate() {
+        if set.contains(&class) != negate {
+            write!(f, "{}", c)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that each rule is written as given and reads back as the same rule.
+    fn assert_round_trip(rules: &[&str]) {
+        for s in rules {
+            let rule = s.parse::<Rule>().unwrap();
+            assert_eq!(rule.to_string(), *s);
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+    }
+
+    fn assert_invalid(rules: &[&str]) {
+        for s in rules {
+            assert!(s.parse::<Rule>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn generations() {
+        assert_round_trip(&["B3/S23", "B2/S/C3", "B278/S3456/C6"]);
+        assert_eq!("B2/S/3".parse::<Rule>().unwrap().to_string(), "B2/S/C3");
+        assert_eq!("/2/3".parse::<Rule>().unwrap().to_string(), "B2/S/C3");
+        assert_eq!("23/3".parse::<Rule>().unwrap().to_string(), "B3/S23");
+        assert_invalid(&["", "B3", "B3/S23/C1", "B3/S23/C257", "B3/S23/Cx", "B9/S23", "B0/S23"]);
+    }
+
+    #[test]
+    fn larger_than_life() {
+        assert_round_trip(&["R5,C0,M1,S34..58,B34..45,NM", "R2,C3,M0,S1..3,S6..6,B2..4,NN"]);
+        assert_eq!("R1,C0,M0,S2..3,B3,NM".parse::<Rule>().unwrap(), Rule::default());
+        assert_invalid(&["R5,C0,M2,S34..58,B34..45,NM", "R5,C0,Mx,S34..58,B34..45,NM",
+                         "R5,C0,M,S34..58,B34..45,NM", "C0,M0,S2..3,B3,NM",
+                         "R51,C0,M0,S2..3,B3,NM", "R2,C0,M0,S2..30,B3,NN",
+                         "R2,C0,M0,S3..2,B3,NM", "R2,C0,M0,S2..3,B3,NX", "R2,C0,M0,S2..3,B3,Q"]);
+    }
+
+    #[test]
+    fn hensel() {
+        assert_round_trip(&["B2-a/S12", "B2ce3aiy/S23-k", "B2a/S", "B3/S2-c3"]);
+        assert_eq!("B2cekin/S23".parse::<Rule>().unwrap().to_string(), "B2-a/S23");
+        assert_eq!("B3/S2cekain3".parse::<Rule>().unwrap().to_string(), "B3/S23");
+        assert_invalid(&["B2z/S23", "B9a/S23", "B0a/S23"]);
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        assert_round_trip(&["B2/S34H", "B1/S1V", "B2/S/C4V", "B246/S1356H"]);
+        assert_eq!("B2/S34h".parse::<Rule>().unwrap().to_string(), "B2/S34H");
+        assert_invalid(&["B7/S34H", "B5/S1V", "B2a/S12V", "B2/S2-aH"]);
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const WIREWORLD: &'static str = include_str!("../rules/WireWorld.rule");
+
+/// A multi-state automaton read from the `@TABLE` and `@COLORS` sections of a Golly `.rule`
+/// file.
+///
+/// Transitions are tried in order and the first match gives the next state of the centre cell;
+/// cells that match no transition keep their state. A variable that appears more than once in
+/// a transition is bound, so all of its occurrences must take the same value.
+#[derive(Clone, Debug)]
+pub struct RuleTable {
+    name: String,
+    states: u32,
+    neighbourhood: TableNeighbourhood,
+    symmetry: Symmetry,
+    transitions: Vec<Transition>,
+    colors: Vec<[u8; 3]>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TableNeighbourhood {
+    Moore,
+    VonNeumann,
+    /// Golly's hexagonal neighbourhood, mapped onto boards with odd rows shifted half a cell to
+    /// the right.
+    Hexagonal,
+    OneDimensional,
+}
+
+#[derive(Clone, Debug)]
+enum Symmetry {
+    /// The transition is tried with the neighbours in each of these orders.
+    Permutations(Vec<Vec<usize>>),
+    /// The transition is tried with the neighbours in any order.
+    Permute,
+}
+
+#[derive(Clone, Debug)]
+struct Transition {
+    inputs: Vec<Input>,
+    output: Output,
+    bound: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Input {
+    states: StateSet,
+    bound: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+enum Output {
+    State(u8),
+    /// The value of the bound variable.
+    Bound(usize),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct StateSet([u64; 4]);
+
+impl StateSet {
+    fn new() -> StateSet {
+        StateSet([0; 4])
+    }
+
+    fn single(state: u8) -> StateSet {
+        let mut set = StateSet::new();
+        set.insert(state);
+        set
+    }
+
+    fn insert(&mut self, state: u8) {
+        self.0[(state / 64) as usize] |= 1 << (state % 64);
+    }
+
+    fn union(&mut self, other: &StateSet) {
+        for (a, b) in self.0.iter_mut().zip(&other.0) {
+            *a |= *b;
+        }
+    }
+
+    fn contains(&self, state: u8) -> bool {
+        (self.0[(state / 64) as usize] & (1 << (state % 64))) != 0
+    }
+}
+
+impl TableNeighbourhood {
+    /// Returns the offsets of the neighbours in the order used by rule tables.
+    pub fn offsets(&self, odd_row: bool) -> &'static [(i32, i32)] {
+        match (*self, odd_row) {
+            (TableNeighbourhood::Moore, _) => {
+                &[(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)]
+            }
+            (TableNeighbourhood::VonNeumann, _) => &[(0, -1), (1, 0), (0, 1), (-1, 0)],
+            // N, E, SE, S, W and NW in Golly are the upper right, right, lower right, lower
+            // left, left and upper left cells on screen.
+            (TableNeighbourhood::Hexagonal, false) => {
+                &[(0, -1), (1, 0), (0, 1), (-1, 1), (-1, 0), (-1, -1)]
+            }
+            (TableNeighbourhood::Hexagonal, true) => {
+                &[(1, -1), (1, 0), (1, 1), (0, 1), (-1, 0), (0, -1)]
+            }
+            (TableNeighbourhood::OneDimensional, _) => &[(-1, 0), (1, 0)],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.offsets(false).len()
+    }
+}
+
+impl RuleTable {
+    /// Loads a rule by name: `WireWorld` is built in, other names are looked up as
+    /// `NAME.rule` in the current directory and in `rules/`. A path ending in `.rule` is read
+    /// directly.
+    pub fn load(name: &str) -> Result<RuleTable, String> {
+        if name.ends_with(".rule") {
+            return RuleTable::load_file(Path::new(name));
+        }
+        if name.eq_ignore_ascii_case("WireWorld") {
+            return RuleTable::parse(WIREWORLD);
+        }
+        let file_name = format!("{}.rule", name);
+        for dir in &[".", "rules"] {
+            let path = Path::new(dir).join(&file_name);
+            if path.exists() {
+                return RuleTable::load_file(&path);
+            }
+        }
+        Err(format!("unknown rule: {}", name))
+    }
+
+    fn load_file(path: &Path) -> Result<RuleTable, String> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        RuleTable::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<RuleTable, String> {
+        let mut name = None;
+        let mut section = "";
+        let mut table = vec![];
+        let mut colors = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.starts_with('@') {
+                let mut it = line.splitn(2, char::is_whitespace);
+                section = it.next().unwrap();
+                if section == "@RULE" {
+                    name = it.next().map(|s| s.trim().to_string());
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            match section {
+                "@TABLE" => table.push((i + 1, line)),
+                "@COLORS" => colors.push((i + 1, line)),
+                _ => {}
+            }
+        }
+        if table.is_empty() {
+            return Err("no @TABLE section".to_string());
+        }
+
+        let mut rule = parse_table(&table)?;
+        rule.name = name.unwrap_or_else(|| "unnamed".to_string());
+        parse_colors(&mut rule, &colors)?;
+        Ok(rule)
+    }
+
+    /// Returns the number of cell states, including the dead state.
+    pub fn states(&self) -> u32 {
+        self.states
+    }
+
+    pub fn neighbourhood(&self) -> TableNeighbourhood {
+        self.neighbourhood
+    }
+
+    pub fn color(&self, state: u8) -> [u8; 3] {
+        self.colors.get(state as usize).cloned().unwrap_or([128, 128, 128])
+    }
+
+    /// Returns the next state of a cell. `cells` holds the cell itself followed by its
+    /// neighbours in the order of `TableNeighbourhood::offsets`.
+    pub fn next_state(&self, cells: &[u8]) -> u8 {
+        for transition in &self.transitions {
+            if let Some(state) = self.apply(transition, cells) {
+                return state;
+            }
+        }
+        cells[0]
+    }
+
+    fn apply(&self, transition: &Transition, cells: &[u8]) -> Option<u8> {
+        let mut bindings = vec![None; transition.bound];
+        if !bind(&transition.inputs[0], cells[0], &mut bindings) {
+            return None;
+        }
+
+        let neighbours = &transition.inputs[1..];
+        let bindings = match self.symmetry {
+            Symmetry::Permutations(ref perms) => {
+                perms.iter()
+                    .filter_map(|perm| {
+                        let mut bindings = bindings.clone();
+                        let matched = perm.iter()
+                            .enumerate()
+                            .all(|(j, &k)| bind(&neighbours[j], cells[k + 1], &mut bindings));
+                        if matched { Some(bindings) } else { None }
+                    })
+                    .next()
+            }
+            Symmetry::Permute => {
+                let mut used = vec![false; neighbours.len()];
+                if permute(neighbours, &cells[1..], &mut used, &mut bindings) {
+                    Some(bindings)
+                } else {
+                    None
+                }
+            }
+        };
+
+        bindings.map(|bindings| match transition.output {
+            Output::State(state) => state,
+            Output::Bound(v) => bindings[v].unwrap(),
+        })
+    }
+}
+
+impl fmt::Display for RuleTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Checks that `state` is accepted by `input` and agrees with the earlier occurrences of its
+/// bound variable.
+fn bind(input: &Input, state: u8, bindings: &mut [Option<u8>]) -> bool {
+    if !input.states.contains(state) {
+        return false;
+    }
+    match input.bound {
+        Some(v) => {
+            match bindings[v] {
+                Some(value) => value == state,
+                None => {
+                    bindings[v] = Some(state);
+                    true
+                }
+            }
+        }
+        None => true,
+    }
+}
+
+/// Tries to assign each of the `inputs` a distinct cell.
+fn permute(inputs: &[Input],
+           cells: &[u8],
+           used: &mut [bool],
+           bindings: &mut Vec<Option<u8>>)
+           -> bool {
+    let input = match inputs.first() {
+        Some(input) => input,
+        None => return true,
+    };
+    for k in 0..cells.len() {
+        if used[k] {
+            continue;
+        }
+        let mut next = bindings.clone();
+        if bind(input, cells[k], &mut next) {
+            used[k] = true;
+            if permute(&inputs[1..], cells, used, &mut next) {
+                *bindings = next;
+                return true;
+            }
+            used[k] = false;
+        }
+    }
+    false
+}
+
+fn parse_table(lines: &[(usize, &str)]) -> Result<RuleTable, String> {
+    let mut states = None;
+    let mut neighbourhood = TableNeighbourhood::Moore;
+    let mut symmetry = "none";
+    let mut vars = HashMap::new();
+    let mut transitions = vec![];
+
+    for &(n, line) in lines {
+        let error = |e: String| format!("line {}: {}", n, e);
+
+        if line.starts_with("var ") || line.starts_with("var\t") {
+            let mut it = line[4..].splitn(2, '=').map(str::trim);
+            let (name, value) = match (it.next(), it.next()) {
+                (Some(name), Some(value)) if !name.is_empty() => (name, value),
+                _ => return Err(error("expected `var name = {states}`".to_string())),
+            };
+            let set = parse_set(value, &vars).map_err(&error)?;
+            vars.insert(name.to_string(), set);
+            continue;
+        }
+
+        let mut it = line.splitn(2, ':').map(str::trim);
+        if let (Some(key), Some(value)) = (it.next(), it.next()) {
+            match key {
+                "n_states" | "num_states" => {
+                    states = match value.parse() {
+                        Ok(n) if n >= 2 && n <= 256 => Some(n),
+                        _ => return Err(error(format!("invalid number of states: {}", value))),
+                    }
+                }
+                "neighborhood" | "neighbourhood" => {
+                    neighbourhood = match value {
+                        "Moore" => TableNeighbourhood::Moore,
+                        "vonNeumann" => TableNeighbourhood::VonNeumann,
+                        "hexagonal" => TableNeighbourhood::Hexagonal,
+                        "oneDimensional" => TableNeighbourhood::OneDimensional,
+                        _ => return Err(error(format!("unsupported neighborhood: {}", value))),
+                    }
+                }
+                "symmetries" => symmetry = value,
+                _ => return Err(error(format!("unknown setting `{}`", key))),
+            }
+            continue;
+        }
+
+        let states = states.ok_or_else(|| error("n_states must come first".to_string()))?;
+        let transition = parse_transition(line, states, neighbourhood.len(), &vars)
+            .map_err(&error)?;
+        transitions.push(transition);
+    }
+
+    let states = states.ok_or_else(|| "missing n_states".to_string())?;
+    Ok(RuleTable {
+        name: String::new(),
+        states: states,
+        neighbourhood: neighbourhood,
+        symmetry: parse_symmetry(neighbourhood, symmetry)?,
+        transitions: transitions,
+        colors: default_colors(states),
+    })
+}
+
+fn parse_transition(line: &str,
+                    states: u32,
+                    neighbours: usize,
+                    vars: &HashMap<String, StateSet>)
+                    -> Result<Transition, String> {
+    // With fewer than 11 states the commas may be omitted.
+    let items = if line.contains(',') {
+        split_items(line)
+    } else if states <= 10 {
+        line.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_string()).collect()
+    } else {
+        vec![line.to_string()]
+    };
+    if items.len() != neighbours + 2 {
+        return Err(format!("expected {} states, found {}", neighbours + 2, items.len()));
+    }
+
+    let (inputs, output) = items.split_at(neighbours + 1);
+    let output = output[0].as_str();
+
+    // Variables are bound if they appear more than once or give the output.
+    let mut bound = HashMap::new();
+    for item in inputs {
+        if vars.contains_key(item) &&
+           (item == output || inputs.iter().filter(|i| *i == item).count() > 1) {
+            let id = bound.len();
+            bound.entry(item.as_str()).or_insert(id);
+        }
+    }
+
+    let inputs = inputs.iter()
+        .map(|item| {
+            Ok(Input {
+                states: parse_set(item, vars)?,
+                bound: bound.get(item.as_str()).cloned(),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let output = match bound.get(output) {
+        Some(&v) => Output::Bound(v),
+        None => {
+            match output.parse() {
+                Ok(state) if (state as u32) < states => Output::State(state),
+                _ => return Err(format!("invalid output `{}`", output)),
+            }
+        }
+    };
+    if inputs.iter().any(|input| (0..256).any(|s| input.states.contains(s as u8) && s >= states)) {
+        return Err("state out of range".to_string());
+    }
+
+    Ok(Transition {
+        inputs: inputs,
+        output: output,
+        bound: bound.len(),
+    })
+}
+
+/// Splits a transition at the commas outside of braces.
+fn split_items(line: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut item = String::new();
+    let mut depth = 0;
+    for c in line.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(item.trim().to_string());
+                item.clear();
+                continue;
+            }
+            _ => {}
+        }
+        item.push(c);
+    }
+    items.push(item.trim().to_string());
+    items
+}
+
+/// Parses a state, a variable name or a `{...}` list of them.
+fn parse_set(s: &str, vars: &HashMap<String, StateSet>) -> Result<StateSet, String> {
+    let s = s.trim();
+    if s.starts_with('{') && s.ends_with('}') {
+        let mut set = StateSet::new();
+        for item in split_items(&s[1..(s.len() - 1)]) {
+            set.union(&parse_set(&item, vars)?);
+        }
+        return Ok(set);
+    }
+    if let Some(set) = vars.get(s) {
+        return Ok(*set);
+    }
+    match s.parse::<u32>() {
+        Ok(state) if state < 256 => Ok(StateSet::single(state as u8)),
+        _ => Err(format!("unknown state or variable `{}`", s)),
+    }
+}
+
+fn parse_symmetry(neighbourhood: TableNeighbourhood, name: &str) -> Result<Symmetry, String> {
+    if name == "permute" {
+        return Ok(Symmetry::Permute);
+    }
+
+    let n = neighbourhood.len();
+    let rotations = |step: usize| {
+        (0..(n / step))
+            .map(|r| (0..n).map(|j| (j + r * step) % n).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+    let reflected = |perms: Vec<Vec<usize>>| {
+        let mirror = perms.iter()
+            .map(|perm| (0..n).map(|j| perm[(n - j) % n]).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        perms.into_iter().chain(mirror).collect::<Vec<_>>()
+    };
+
+    use self::TableNeighbourhood::*;
+    let perms = match (neighbourhood, name) {
+        (_, "none") => rotations(n),
+        (Moore, "rotate4") => rotations(2),
+        (Moore, "rotate8") => rotations(1),
+        (Moore, "rotate4reflect") => reflected(rotations(2)),
+        (Moore, "rotate8reflect") => reflected(rotations(1)),
+        (Moore, "reflect_horizontal") |
+        (VonNeumann, "reflect_horizontal") => reflected(rotations(n)),
+        (VonNeumann, "rotate4") => rotations(1),
+        (VonNeumann, "rotate4reflect") => reflected(rotations(1)),
+        (Hexagonal, "rotate2") => rotations(3),
+        (Hexagonal, "rotate3") => rotations(2),
+        (Hexagonal, "rotate6") => rotations(1),
+        (Hexagonal, "rotate6reflect") => reflected(rotations(1)),
+        (OneDimensional, "reflect") => vec![vec![0, 1], vec![1, 0]],
+        _ => return Err(format!("unsupported symmetries: {}", name)),
+    };
+    Ok(Symmetry::Permutations(perms))
+}
+
+/// Parses `state r g b` lines, or `r1 g1 b1 r2 g2 b2` for a gradient over the live states.
+fn parse_colors(rule: &mut RuleTable, lines: &[(usize, &str)]) -> Result<(), String> {
+    for &(n, line) in lines {
+        let nums = line.split_whitespace()
+            .map(|s| s.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("line {}: invalid color", n))?;
+        if nums.iter().any(|&v| v > 255) {
+            return Err(format!("line {}: invalid color", n));
+        }
+        let rgb = |v: &[u32]| [v[0] as u8, v[1] as u8, v[2] as u8];
+        if nums.len() == 6 {
+            rule.colors = gradient(rule.states, rgb(&nums[0..3]), rgb(&nums[3..6]));
+        } else if nums.len() >= 4 {
+            let color = rgb(&nums[(nums.len() - 3)..]);
+            for &state in &nums[..(nums.len() - 3)] {
+                if let Some(c) = rule.colors.get_mut(state as usize) {
+                    *c = color;
+                }
+            }
+        } else {
+            return Err(format!("line {}: invalid color", n));
+        }
+    }
+    Ok(())
+}
+
+fn default_colors(states: u32) -> Vec<[u8; 3]> {
+    gradient(states, [255, 0, 0], [255, 255, 0])
+}
+
+/// Returns black for the dead state followed by a gradient over the live states.
+fn gradient(states: u32, from: [u8; 3], to: [u8; 3]) -> Vec<[u8; 3]> {
+    let mut colors = vec![[0, 0, 0]];
+    for state in 1..states {
+        let t = if states > 2 {
+            ((state - 1) as f64) / ((states - 2) as f64)
+        } else {
+            0.0
+        };
+        let mix = |i: usize| ((from[i] as f64) + ((to[i] as f64) - (from[i] as f64)) * t) as u8;
+        colors.push([mix(0), mix(1), mix(2)]);
+    }
+    colors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wireworld() {
+        let rule = RuleTable::load("WireWorld").unwrap();
+        assert_eq!(rule.to_string(), "WireWorld");
+        assert_eq!(rule.states(), 4);
+        assert_eq!(rule.next_state(&[0, 1, 1, 0, 0, 0, 0, 0, 0]), 0);
+        assert_eq!(rule.next_state(&[1, 3, 3, 0, 0, 0, 0, 0, 0]), 2);
+        assert_eq!(rule.next_state(&[2, 1, 0, 0, 0, 0, 0, 0, 0]), 3);
+        assert_eq!(rule.next_state(&[3, 0, 0, 0, 0, 0, 1, 2, 3]), 1);
+        assert_eq!(rule.next_state(&[3, 1, 0, 0, 1, 0, 0, 0, 0]), 1);
+        assert_eq!(rule.next_state(&[3, 1, 1, 1, 0, 0, 0, 0, 0]), 3);
+        assert_eq!(rule.next_state(&[3, 2, 2, 0, 0, 0, 0, 0, 0]), 3);
+    }
+
+    #[test]
+    fn symmetries_and_bound_variables() {
+        let rule = RuleTable::parse("@RULE Test\n\
+                                     @TABLE\n\
+                                     n_states:3\n\
+                                     neighborhood:vonNeumann\n\
+                                     symmetries:rotate4\n\
+                                     var a={1,2}\n\
+                                     var b={0,1,2}\n\
+                                     0,a,a,b,b,a\n\
+                                     0,1,0,0,0,2\n")
+            .unwrap();
+        assert_eq!(rule.neighbourhood(), TableNeighbourhood::VonNeumann);
+        // The first transition copies the state of two neighbouring cells that agree, if the
+        // other two agree as well.
+        assert_eq!(rule.next_state(&[0, 2, 2, 1, 1]), 2);
+        assert_eq!(rule.next_state(&[0, 0, 1, 1, 0]), 1);
+        assert_eq!(rule.next_state(&[0, 2, 2, 0, 1]), 0);
+        assert_eq!(rule.next_state(&[0, 1, 2, 0, 0]), 0);
+        // The second applies in each rotation.
+        assert_eq!(rule.next_state(&[0, 0, 0, 1, 0]), 2);
+        assert_eq!(rule.next_state(&[0, 0, 0, 0, 0]), 0);
+    }
+
+    #[test]
+    fn reject_malformed_tables() {
+        let invalid = ["@RULE Empty\n",
+                       "@TABLE\nneighborhood:Moore\n0,1,1,1,0,0,0,0,0,1\n",
+                       "@TABLE\nn_states:2\n0,1,1,1,0,0,0,0,1\n",
+                       "@TABLE\nn_states:2\n0,1,1,1,0,0,0,0,0,2\n",
+                       "@TABLE\nn_states:2\n0,x,1,1,0,0,0,0,0,1\n",
+                       "@TABLE\nn_states:2\nneighborhood:Moore\nsymmetries:rotate6\n",
+                       "@TABLE\nn_states:2\n0,0,0,0,0,0,0,0,0,0\n@COLORS\n1 255 0\n"];
+        for content in &invalid {
+            assert!(RuleTable::parse(content).is_err(), "{}", content);
+        }
+    }
+}
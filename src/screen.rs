@@ -0,0 +1,74 @@
+use app::App;
+use graphics::{self, ImageSize, Transformed};
+use graphics::character::CharacterCache;
+use opengl_graphics::{GlGraphics, OpenGL, Texture, TextureSettings};
+use opengl_graphics::glyph_cache::GlyphCache;
+use piston::input::RenderArgs;
+
+const HUD_FONT: &'static [u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
+const HUD_FONT_SIZE: u32 = 14;
+const HUD_LINE_HEIGHT: f64 = 18.0;
+const HUD_MARGIN: f64 = 8.0;
+
+/// Shows the view of an `App` in an OpenGL window, with the heads-up display on top.
+pub struct Screen {
+    gl_graphics: GlGraphics,
+    glyph_cache: GlyphCache<'static>,
+    texture: Option<Texture>,
+}
+
+impl Screen {
+    pub fn new(opengl: OpenGL) -> Screen {
+        let glyph_cache = GlyphCache::from_bytes(HUD_FONT, TextureSettings::new())
+            .expect("failed to load HUD font");
+
+        Screen {
+            gl_graphics: GlGraphics::new(opengl),
+            glyph_cache: glyph_cache,
+            texture: None,
+        }
+    }
+
+    pub fn draw(&mut self, app: &mut App, args: RenderArgs) {
+        if let Some(canvas) = app.render() {
+            let resized = self.texture
+                .as_ref()
+                .map_or(true, |texture| texture.get_size() != canvas.dimensions());
+            if resized {
+                self.texture = Some(Texture::from_image(canvas, &TextureSettings::new()));
+            } else if let Some(ref mut texture) = self.texture {
+                texture.update(canvas);
+            }
+        }
+        let texture = match self.texture {
+            Some(ref texture) => texture,
+            None => return,
+        };
+        let hud = app.hud_lines();
+        let glyph_cache = &mut self.glyph_cache;
+
+        self.gl_graphics.draw(args.viewport(), |ctx, g2d| {
+            graphics::image(texture, ctx.transform, g2d);
+
+            if !hud.is_empty() {
+                let width = hud.iter()
+                    .map(|line| glyph_cache.width(HUD_FONT_SIZE, line))
+                    .fold(0.0, f64::max);
+                let height = HUD_LINE_HEIGHT * (hud.len() as f64);
+                graphics::rectangle([0.0, 0.0, 0.0, 0.6],
+                                    [0.0, 0.0, width + HUD_MARGIN * 2.0, height + HUD_MARGIN * 2.0],
+                                    ctx.transform,
+                                    g2d);
+                for (i, line) in hud.iter().enumerate() {
+                    let y = HUD_MARGIN + HUD_LINE_HEIGHT * ((i + 1) as f64) - 4.0;
+                    graphics::text([1.0, 1.0, 1.0, 1.0],
+                                   HUD_FONT_SIZE,
+                                   line,
+                                   glyph_cache,
+                                   ctx.transform.trans(HUD_MARGIN, y),
+                                   g2d);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,264 @@
+use geom::{Move, Point, Size};
+use grid::{self, EdgeMode};
+use pattern::Pattern;
+use render::Aggregate;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const HEADER: &'static str = "# game-of-life-rs session";
+
+/// Everything needed to bring the viewer back to where it was.
+///
+/// A session file consists of `key = value` lines, an empty line and the board in RLE format,
+/// which also holds the rule:
+///
+/// ```text
+/// # game-of-life-rs session
+/// generation = 120
+/// running = false
+/// origin = -64, 0
+/// offset = 12, -40
+/// scale = 4
+///
+/// x = 256, y = 192, rule = B3/S23
+/// ...
+/// ```
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub pattern: Pattern,
+    pub origin: Point,
+    pub edge: Option<EdgeMode>,
+    pub max_size: Option<Size>,
+    pub generation: u64,
+    pub running: bool,
+    pub offset: Move,
+    pub scale: f64,
+    pub aggregate: Aggregate,
+    pub smooth_zoom: bool,
+    pub show_hud: bool,
+    pub show_minimap: bool,
+    pub draw_state: u8,
+}
+
+impl Session {
+    /// Returns the location used by the save and load session commands.
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("session.txt"))
+    }
+
+    /// Returns the location of the session saved on exit with `--autosave`.
+    pub fn autosave_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("autosave.txt"))
+    }
+
+    pub fn load(path: &Path) -> Result<Session, String> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Session::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Saves the session, creating the directory if needed.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let result = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir),
+            _ => Ok(()),
+        };
+        result.and_then(|_| File::create(path))
+            .and_then(|mut f| f.write_all(self.to_string().as_bytes()))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(s: &str) -> Result<Session, String> {
+        let s = s.replace("\r\n", "\n");
+        let (settings, board) = match s.find("\n\n") {
+            Some(i) => (&s[..i], &s[i + 2..]),
+            None => return Err("missing board".to_string()),
+        };
+        if !settings.starts_with(HEADER) {
+            return Err("not a session file".to_string());
+        }
+
+        let mut session = Session {
+            pattern: Pattern::parse_rle(board)?,
+            origin: Point(0, 0),
+            edge: None,
+            max_size: None,
+            generation: 0,
+            running: false,
+            offset: Move(0, 0),
+            scale: 1.0,
+            aggregate: Aggregate::AnyAlive,
+            smooth_zoom: true,
+            show_hud: true,
+            show_minimap: true,
+            draw_state: 1,
+        };
+        for (i, line) in settings.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut it = line.splitn(2, '=').map(str::trim);
+            let (key, value) = match (it.next(), it.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => return Err(format!("line {}: expected `key = value`", i + 1)),
+            };
+            let invalid = || format!("line {}: invalid {}: {}", i + 1, key, value);
+            match key {
+                "generation" => session.generation = parse(value).ok_or_else(&invalid)?,
+                "running" => session.running = parse(value).ok_or_else(&invalid)?,
+                "origin" => {
+                    let (x, y) = parse_pair(value).ok_or_else(&invalid)?;
+                    session.origin = Point(x, y);
+                }
+                "edge" => session.edge = Some(value.parse()?),
+                "expand" => {
+                    session.max_size = match value {
+                        "none" => None,
+                        _ => Some(grid::parse_size(value)?),
+                    }
+                }
+                "offset" => {
+                    let (x, y) = parse_pair(value).ok_or_else(&invalid)?;
+                    session.offset = Move(x, y);
+                }
+                "scale" => {
+                    session.scale = match parse(value) {
+                        Some(scale) if scale > 0.0 => scale,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "density" => {
+                    session.aggregate = if parse(value).ok_or_else(&invalid)? {
+                        Aggregate::Density
+                    } else {
+                        Aggregate::AnyAlive
+                    }
+                }
+                "smooth-zoom" => session.smooth_zoom = parse(value).ok_or_else(&invalid)?,
+                "hud" => session.show_hud = parse(value).ok_or_else(&invalid)?,
+                "minimap" => session.show_minimap = parse(value).ok_or_else(&invalid)?,
+                "draw-state" => session.draw_state = parse(value).ok_or_else(&invalid)?,
+                _ => return Err(format!("line {}: unknown key `{}`", i + 1, key)),
+            }
+        }
+        Ok(session)
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "generation = {}", self.generation)?;
+        writeln!(f, "running = {}", self.running)?;
+        writeln!(f, "origin = {}, {}", self.origin.0, self.origin.1)?;
+        if let Some(edge) = self.edge {
+            writeln!(f, "edge = {}", edge)?;
+        }
+        match self.max_size {
+            Some(size) => writeln!(f, "expand = {}x{}", size.0, size.1)?,
+            None => writeln!(f, "expand = none")?,
+        }
+        writeln!(f, "offset = {}, {}", self.offset.0, self.offset.1)?;
+        writeln!(f, "scale = {}", self.scale)?;
+        writeln!(f, "density = {}", self.aggregate == Aggregate::Density)?;
+        writeln!(f, "smooth-zoom = {}", self.smooth_zoom)?;
+        writeln!(f, "hud = {}", self.show_hud)?;
+        writeln!(f, "minimap = {}", self.show_minimap)?;
+        writeln!(f, "draw-state = {}", self.draw_state)?;
+        writeln!(f)?;
+        write!(f, "{}", self.pattern.to_rle())
+    }
+}
+
+/// Returns the directory holding the bindings file and saved sessions.
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("game-of-life-rs"));
+    }
+    if let Some(dir) = env::var_os("APPDATA") {
+        return Some(PathBuf::from(dir).join("game-of-life-rs"));
+    }
+    env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".config").join("game-of-life-rs"))
+}
+
+fn parse<T>(s: &str) -> Option<T>
+    where T: FromStr
+{
+    s.parse().ok()
+}
+
+fn parse_pair(s: &str) -> Option<(i32, i32)> {
+    let mut it = s.split(',').map(str::trim);
+    match (it.next(), it.next(), it.next()) {
+        (Some(x), Some(y), None) => Some((parse(x)?, parse(y)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: &'static str = "# game-of-life-rs session
+generation = 120
+running = false
+origin = -64, 3
+edge = noisy:0.25
+expand = 512x384
+offset = 12, -40
+scale = 2.5
+density = true
+smooth-zoom = false
+hud = true
+minimap = false
+draw-state = 2
+
+x = 6, y = 3, rule = B2/S/C3
+.A$2.B$3A!
+";
+
+    #[test]
+    fn round_trip() {
+        let session = Session::parse(SESSION).unwrap();
+        assert_eq!(session.generation, 120);
+        assert_eq!(session.origin, Point(-64, 3));
+        assert_eq!(session.edge, Some(EdgeMode::Noisy(0.25)));
+        assert_eq!(session.max_size, Some(Size(512, 384)));
+        assert_eq!(session.offset, Move(12, -40));
+        assert_eq!(session.scale, 2.5);
+        assert_eq!(session.aggregate, Aggregate::Density);
+        assert_eq!((session.smooth_zoom, session.show_hud, session.show_minimap),
+                   (false, true, false));
+        assert_eq!(session.draw_state, 2);
+        assert_eq!(session.pattern.size(), Size(6, 3));
+
+        assert_eq!(session.to_string(), SESSION);
+        let parsed = Session::parse(&session.to_string()).unwrap();
+        assert_eq!(parsed.to_string(), session.to_string());
+    }
+
+    #[test]
+    fn reject_malformed_sessions() {
+        let replace = |from: &str, to: &str| SESSION.replace(from, to);
+        let invalid = [replace("# game-of-life-rs session", "# something else"),
+                       replace("\n\n", "\n"),
+                       replace("running = false", "running = maybe"),
+                       replace("origin = -64, 3", "origin = -64"),
+                       replace("scale = 2.5", "scale = 0"),
+                       replace("expand = 512x384", "expand = 512"),
+                       replace("edge = noisy:0.25", "edge = sideways"),
+                       replace("hud = true", "hud"),
+                       replace("hud = true", "colour = blue"),
+                       replace("3A!", "3Z?!")];
+        for s in &invalid {
+            assert!(Session::parse(s).is_err(), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use geom::{Move, Point};
+use std::cmp;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Tool {
+    Freehand,
+    Line,
+    Rectangle { filled: bool },
+    Ellipse { filled: bool },
+}
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Tool::Freehand => "freehand",
+            Tool::Line => "line",
+            Tool::Rectangle { filled: false } => "rectangle",
+            Tool::Rectangle { filled: true } => "filled rectangle",
+            Tool::Ellipse { filled: false } => "ellipse",
+            Tool::Ellipse { filled: true } => "filled ellipse",
+        }
+    }
+
+    /// Returns the cells covered by the shape dragged from `p0` to `p1`.
+    ///
+    /// If `constrain` is set, lines snap to horizontal, vertical or diagonal directions and
+    /// rectangles and ellipses become squares and circles.
+    pub fn cells(&self, p0: Point, p1: Point, constrain: bool) -> Vec<Point> {
+        match *self {
+            Tool::Freehand => vec![p1],
+            Tool::Line => {
+                let p1 = if constrain { snap_line(p0, p1) } else { p1 };
+                line(p0, p1)
+            }
+            Tool::Rectangle { filled } => {
+                let p1 = if constrain { snap_square(p0, p1) } else { p1 };
+                rectangle(p0, p1, filled)
+            }
+            Tool::Ellipse { filled } => {
+                let p1 = if constrain { snap_square(p0, p1) } else { p1 };
+                ellipse(p0, p1, filled)
+            }
+        }
+    }
+}
+
+pub fn line(p0: Point, p1: Point) -> Vec<Point> {
+    let d = p1 - p0;
+    let n = cmp::max(d.0.abs(), d.1.abs());
+    if n == 0 {
+        return vec![p0];
+    }
+    (0..(n + 1))
+        .map(|i| {
+            Point(p0.0 + div_round(d.0 * i, n), p0.1 + div_round(d.1 * i, n))
+        })
+        .collect()
+}
+
+pub fn rectangle(p0: Point, p1: Point, filled: bool) -> Vec<Point> {
+    let (x0, x1) = (cmp::min(p0.0, p1.0), cmp::max(p0.0, p1.0));
+    let (y0, y1) = (cmp::min(p0.1, p1.1), cmp::max(p0.1, p1.1));
+    let mut cells = vec![];
+    for y in y0..(y1 + 1) {
+        for x in x0..(x1 + 1) {
+            if filled || x == x0 || x == x1 || y == y0 || y == y1 {
+                cells.push(Point(x, y));
+            }
+        }
+    }
+    cells
+}
+
+/// Returns the cells of the ellipse inscribed in the bounding box of `p0` and `p1`.
+pub fn ellipse(p0: Point, p1: Point, filled: bool) -> Vec<Point> {
+    let (x0, x1) = (cmp::min(p0.0, p1.0), cmp::max(p0.0, p1.0));
+    let (y0, y1) = (cmp::min(p0.1, p1.1), cmp::max(p0.1, p1.1));
+    let center = ((x0 + x1) as f64 / 2.0, (y0 + y1) as f64 / 2.0);
+    let radius = ((x1 - x0 + 1) as f64 / 2.0, (y1 - y0 + 1) as f64 / 2.0);
+    let inside = |x: i32, y: i32| {
+        let dx = (x as f64 - center.0) / radius.0;
+        let dy = (y as f64 - center.1) / radius.1;
+        dx * dx + dy * dy <= 1.0
+    };
+
+    let mut cells = vec![];
+    for y in y0..(y1 + 1) {
+        for x in x0..(x1 + 1) {
+            if !inside(x, y) {
+                continue;
+            }
+            if filled || !inside(x - 1, y) || !inside(x + 1, y) || !inside(x, y - 1) ||
+               !inside(x, y + 1) {
+                cells.push(Point(x, y));
+            }
+        }
+    }
+    cells
+}
+
+fn snap_line(p0: Point, p1: Point) -> Point {
+    let d = p1 - p0;
+    let (a0, a1) = (d.0.abs(), d.1.abs());
+    if a0 > a1 * 2 {
+        p0 + Move(d.0, 0)
+    } else if a1 > a0 * 2 {
+        p0 + Move(0, d.1)
+    } else {
+        let n = cmp::max(a0, a1);
+        p0 + Move(d.0.signum() * n, d.1.signum() * n)
+    }
+}
+
+fn snap_square(p0: Point, p1: Point) -> Point {
+    let d = p1 - p0;
+    let n = cmp::max(d.0.abs(), d.1.abs());
+    p0 + Move(sign(d.0) * n, sign(d.1) * n)
+}
+
+/// Like `signum`, but treats zero as positive so that a square never collapses to a line.
+fn sign(n: i32) -> i32 {
+    if n < 0 { -1 } else { 1 }
+}
+
+fn div_round(a: i32, b: i32) -> i32 {
+    ((a as f64) / (b as f64)).round() as i32
+}
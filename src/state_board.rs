@@ -0,0 +1,118 @@
+use geom::{Point, Size};
+use rand::Rng;
+use rule_table::RuleTable;
+use std::{cmp, mem};
+use std::collections::HashMap;
+
+/// A board with one byte per cell, stepped by a rule table.
+#[derive(Clone, Debug)]
+pub struct StateBoard {
+    size: Size,
+    cells: Vec<u8>,
+    buffer: Vec<u8>,
+    rule: RuleTable,
+    // Next states of the neighbourhoods seen so far. Unused trailing cells are zero.
+    cache: HashMap<[u8; 9], u8>,
+}
+
+impl StateBoard {
+    pub fn new_empty(size: Size, rule: RuleTable) -> Self {
+        let len = (size.0 * size.1) as usize;
+        StateBoard {
+            size: size,
+            cells: vec![0; len],
+            buffer: vec![0; len],
+            rule: rule,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn rule(&self) -> &RuleTable {
+        &self.rule
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        0 <= p.0 && p.0 < self.size.0 && 0 <= p.1 && p.1 < self.size.1
+    }
+
+    pub fn state(&self, p: Point) -> u8 {
+        self.cells[self.index(p)]
+    }
+
+    /// Sets the state of the cell. States beyond the rule's last state are treated as dead.
+    pub fn set_state(&mut self, p: Point, state: u8) {
+        let i = self.index(p);
+        self.cells[i] = if (state as u32) < self.rule.states() {
+            state
+        } else {
+            0
+        };
+    }
+
+    pub fn clear(&mut self) {
+        for v in &mut self.cells {
+            *v = 0;
+        }
+    }
+
+    pub fn random_init<R>(&mut self, rng: &mut R)
+        where R: Rng
+    {
+        let states = self.rule.states();
+        for v in &mut self.cells {
+            *v = rng.gen_range(0, states) as u8;
+        }
+    }
+
+    /// Returns the number of cells in a non-dead state.
+    pub fn population(&self) -> u32 {
+        self.cells.iter().filter(|&&v| v != 0).count() as u32
+    }
+
+    pub fn count_in_rect(&self, p: Point, size: Size) -> u32 {
+        let x0 = cmp::max(p.0, 0);
+        let x1 = cmp::min(p.0 + size.0, self.size.0);
+        let mut count = 0;
+        for y in cmp::max(p.1, 0)..cmp::min(p.1 + size.1, self.size.1) {
+            for x in x0..x1 {
+                if self.cells[self.index(Point(x, y))] != 0 {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Computes the next generation. Cells outside of the board are in state 0.
+    pub fn grow(&mut self) {
+        let neighbourhood = self.rule.neighbourhood();
+        for y in 0..self.size.1 {
+            let offsets = neighbourhood.offsets(y % 2 != 0);
+            for x in 0..self.size.0 {
+                let mut key = [0; 9];
+                key[0] = self.cells[self.index(Point(x, y))];
+                for (k, &(dx, dy)) in offsets.iter().enumerate() {
+                    let p = Point(x + dx, y + dy);
+                    if self.contains(p) {
+                        key[k + 1] = self.cells[self.index(p)];
+                    }
+                }
+
+                let rule = &self.rule;
+                let n = offsets.len() + 1;
+                let next = *self.cache.entry(key).or_insert_with(|| rule.next_state(&key[..n]));
+                let i = self.index(Point(x, y));
+                self.buffer[i] = next;
+            }
+        }
+        mem::swap(&mut self.cells, &mut self.buffer);
+    }
+
+    fn index(&self, p: Point) -> usize {
+        (p.1 * self.size.0 + p.0) as usize
+    }
+}
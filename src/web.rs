@@ -0,0 +1,366 @@
+//! Entry points for the WebAssembly build.
+//!
+//! The page (`docs/game-of-life.js`) owns the event loop: it forwards input events as they arrive
+//! and calls `gol_frame` from `requestAnimationFrame`, which advances the app and hands back the
+//! rendered view for drawing onto an HTML canvas.
+
+use app::{App, AppSettings, Touch};
+use geom::{Move, Point, Size};
+use link::Link;
+use pattern::Pattern;
+use rand::{SeedableRng, XorShiftRng};
+use session::Session;
+use shape::Tool;
+use std::cell::RefCell;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::str;
+
+struct Game {
+    app: App,
+    rng: XorShiftRng,
+    last_time: Option<f64>,
+    hud: String,
+    link: String,
+    session: String,
+    error: String,
+}
+
+impl Game {
+    /// Replaces the app with a new one showing a random board, for a fresh start.
+    fn reset(&mut self) {
+        self.app = new_app(self.app.win_size());
+        self.app.random_init(&mut self.rng);
+    }
+}
+
+thread_local! {
+    static GAME: RefCell<Option<Game>> = RefCell::new(None);
+}
+
+/// Runs `f` on the game, doing nothing before `gol_start` has been called.
+fn with_game<F, T>(f: F) -> Option<T>
+    where F: FnOnce(&mut Game) -> T
+{
+    GAME.with(|game| game.borrow_mut().as_mut().map(f))
+}
+
+/// Runs `f` on the game, keeping the error message for `gol_error` if it fails.
+fn try_with_game<F>(f: F) -> bool
+    where F: FnOnce(&mut Game) -> Result<(), String>
+{
+    with_game(|game| match f(game) {
+        Ok(()) => true,
+        Err(e) => {
+            game.error = e;
+            false
+        }
+    })
+        .unwrap_or(false)
+}
+
+/// Reads a string passed in as `ptr[..len]`.
+unsafe fn str_arg<'a>(ptr: *const u8, len: usize) -> Result<&'a str, String> {
+    str::from_utf8(slice::from_raw_parts(ptr, len)).map_err(|e| e.to_string())
+}
+
+/// Allocates `len` bytes for passing a string in; the caller frees them with `gol_free`.
+#[no_mangle]
+pub extern "C" fn gol_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    mem::forget(buf);
+    ptr
+}
+
+/// Frees memory from `gol_alloc`, given the pointer it returned and the length it was passed.
+#[no_mangle]
+pub unsafe extern "C" fn gol_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+fn new_app(win_size: Size) -> App {
+    let mut settings = AppSettings::default();
+    settings.win_size = win_size;
+    settings.board_size = Size(win_size.0 / settings.rect_size, win_size.1 / settings.rect_size);
+    App::new(&settings)
+}
+
+/// Starts the app on a `width` x `height` canvas with an empty board, to be filled by
+/// `gol_restore` or `gol_reset`. The seed must not be all zeros.
+#[no_mangle]
+pub extern "C" fn gol_start(width: i32, height: i32, s0: u32, s1: u32, s2: u32, s3: u32) {
+    let game = Game {
+        app: new_app(Size(width, height)),
+        rng: XorShiftRng::from_seed([s0, s1, s2, s3]),
+        last_time: None,
+        hud: String::new(),
+        link: String::new(),
+        session: String::new(),
+        error: String::new(),
+    };
+    GAME.with(|cell| *cell.borrow_mut() = Some(game));
+}
+
+/// Starts over with a random board and the default settings and view.
+#[no_mangle]
+pub extern "C" fn gol_reset() {
+    with_game(|game| game.reset());
+}
+
+#[no_mangle]
+pub extern "C" fn gol_resize(width: i32, height: i32) {
+    with_game(|game| game.app.set_win_size(Size(width, height)));
+}
+
+/// Advances the app to `time`, the `requestAnimationFrame` timestamp in milliseconds.
+///
+/// Returns the RGBA pixels of the view if it changed since the last frame, or null. They stay
+/// valid until the next call.
+#[no_mangle]
+pub extern "C" fn gol_frame(time: f64) -> *const u8 {
+    with_game(|game| {
+        let dt = game.last_time.map_or(0.0, |last| (time - last) / 1000.0);
+        game.last_time = Some(time);
+        game.app.update(dt);
+        game.hud = game.app.hud_lines().join("\n");
+        match game.app.render() {
+            Some(canvas) => canvas.as_ptr(),
+            None => ptr::null(),
+        }
+    })
+        .unwrap_or(ptr::null())
+}
+
+/// Returns the heads-up display as UTF-8 text, one line per row, of `gol_hud_len` bytes.
+#[no_mangle]
+pub extern "C" fn gol_hud() -> *const u8 {
+    with_game(|game| game.hud.as_ptr()).unwrap_or(ptr::null())
+}
+
+#[no_mangle]
+pub extern "C" fn gol_hud_len() -> usize {
+    with_game(|game| game.hud.len()).unwrap_or(0)
+}
+
+/// Returns the message of the last failed call as UTF-8 text of `gol_error_len` bytes.
+#[no_mangle]
+pub extern "C" fn gol_error() -> *const u8 {
+    with_game(|game| game.error.as_ptr()).unwrap_or(ptr::null())
+}
+
+#[no_mangle]
+pub extern "C" fn gol_error_len() -> usize {
+    with_game(|game| game.error.len()).unwrap_or(0)
+}
+
+/// Replaces the board with the pattern in `ptr[..len]`, given in RLE or any other format
+/// `Pattern::parse` reads. Returns whether it succeeded.
+#[no_mangle]
+pub unsafe extern "C" fn gol_load_rle(ptr: *const u8, len: usize) -> bool {
+    let text = str_arg(ptr, len);
+    try_with_game(|game| {
+        let pattern = Pattern::parse(text?)?;
+        game.app.load_pattern(&pattern);
+        Ok(())
+    })
+}
+
+/// Sets the rule to the rule string in `ptr[..len]`. Returns whether it succeeded.
+#[no_mangle]
+pub unsafe extern "C" fn gol_set_rule(ptr: *const u8, len: usize) -> bool {
+    let text = str_arg(ptr, len);
+    try_with_game(|game| {
+        game.app.set_rule(text?.parse()?);
+        Ok(())
+    })
+}
+
+/// Loads the pattern and view in a URL fragment (see `Link`). Returns whether it succeeded.
+#[no_mangle]
+pub unsafe extern "C" fn gol_open_link(ptr: *const u8, len: usize) -> bool {
+    let text = str_arg(ptr, len);
+    try_with_game(|game| {
+        let link = Link::parse(text?)?;
+        game.app.open_link(&link);
+        Ok(())
+    })
+}
+
+/// Describes the board and the view in a URL fragment, returned as UTF-8 text of `gol_link_len`
+/// bytes.
+#[no_mangle]
+pub extern "C" fn gol_link() -> *const u8 {
+    with_game(|game| {
+        game.link = game.app.link().to_string();
+        game.link.as_ptr()
+    })
+        .unwrap_or(ptr::null())
+}
+
+#[no_mangle]
+pub extern "C" fn gol_link_len() -> usize {
+    with_game(|game| game.link.len()).unwrap_or(0)
+}
+
+/// Restores the board, settings and view from a session saved by `gol_session`. Returns whether
+/// it succeeded.
+#[no_mangle]
+pub unsafe extern "C" fn gol_restore(ptr: *const u8, len: usize) -> bool {
+    let text = str_arg(ptr, len);
+    try_with_game(|game| {
+        let session = Session::parse(text?)?;
+        game.app.restore(&session);
+        Ok(())
+    })
+}
+
+/// Captures the board, settings and view in the session format, returned as UTF-8 text of
+/// `gol_session_len` bytes.
+#[no_mangle]
+pub extern "C" fn gol_session() -> *const u8 {
+    with_game(|game| {
+        game.session = game.app.session().to_string();
+        game.session.as_ptr()
+    })
+        .unwrap_or(ptr::null())
+}
+
+#[no_mangle]
+pub extern "C" fn gol_session_len() -> usize {
+    with_game(|game| game.session.len()).unwrap_or(0)
+}
+
+#[no_mangle]
+pub extern "C" fn gol_clear() {
+    with_game(|game| game.app.clear());
+}
+
+#[no_mangle]
+pub extern "C" fn gol_random() {
+    with_game(|game| game.app.random_init(&mut game.rng));
+}
+
+#[no_mangle]
+pub extern "C" fn gol_toggle_running() {
+    with_game(|game| game.app.toggle_running());
+}
+
+#[no_mangle]
+pub extern "C" fn gol_is_running() -> bool {
+    with_game(|game| game.app.is_running()).unwrap_or(false)
+}
+
+/// Advances the board by one generation if it is paused.
+#[no_mangle]
+pub extern "C" fn gol_step() {
+    with_game(|game| game.app.step());
+}
+
+/// Returns the generation, as a double so that JavaScript gets a plain number.
+#[no_mangle]
+pub extern "C" fn gol_generation() -> f64 {
+    with_game(|game| game.app.generation() as f64).unwrap_or(0.0)
+}
+
+#[no_mangle]
+pub extern "C" fn gol_population() -> u32 {
+    with_game(|game| game.app.population()).unwrap_or(0)
+}
+
+#[no_mangle]
+pub extern "C" fn gol_mouse_move(x: i32, y: i32) {
+    with_game(|game| game.app.mouse_move(Point(x, y)));
+}
+
+/// Handles a press or release of `MouseEvent.button`.
+#[no_mangle]
+pub extern "C" fn gol_mouse_button(button: u32, pressed: bool) {
+    with_game(|game| match button {
+        0 => game.app.drawing(pressed),
+        1 => game.app.moving(pressed),
+        2 => game.app.erasing(pressed),
+        _ => {}
+    });
+}
+
+/// Handles a change of the touch with `Touch.identifier` `id` at `x`, `y` in the canvas, where
+/// `phase` is 0 for start, 1 for move, 2 for end and 3 for cancel.
+///
+/// Only the two-finger gestures are handled here; as with SDL on the desktop, the page also sends
+/// a single touch as mouse events.
+#[no_mangle]
+pub extern "C" fn gol_touch(id: i32, x: f64, y: f64, phase: u32) {
+    let touch = match phase {
+        0 => Touch::Start,
+        1 => Touch::Move,
+        2 => Touch::End,
+        _ => Touch::Cancel,
+    };
+    with_game(|game| {
+        let size = game.app.win_size();
+        let pos = [x / (size.0 as f64), y / (size.1 as f64)];
+        game.app.touch(id as i64, pos, touch);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn gol_wheel(steps: f64) {
+    with_game(|game| game.app.zoom(steps));
+}
+
+/// Handles a press or release of the key with the `KeyboardEvent.code` in `ptr[..len]`.
+///
+/// Returns whether the key does something, so that the page can keep the browser from acting
+/// on it too.
+#[no_mangle]
+pub unsafe extern "C" fn gol_key(ptr: *const u8, len: usize, pressed: bool) -> bool {
+    let code = match str_arg(ptr, len) {
+        Ok(code) => code,
+        Err(_) => return false,
+    };
+    with_game(|game| key(game, code, pressed)).unwrap_or(false)
+}
+
+/// Runs the action of a key, following the default bindings of the native version. `G`, which
+/// is unbound there, starts over as on a first visit.
+fn key(game: &mut Game, code: &str, pressed: bool) -> bool {
+    let dir = if pressed { 1 } else { -1 };
+    match code {
+        "ArrowRight" => game.app.slide(Move(dir, 0)),
+        "ArrowLeft" => game.app.slide(Move(-dir, 0)),
+        "ArrowUp" => game.app.slide(Move(0, -dir)),
+        "ArrowDown" => game.app.slide(Move(0, dir)),
+        "ShiftLeft" | "ShiftRight" => game.app.constrain(pressed),
+        _ => {
+            let action: fn(&mut Game) = match code {
+                "KeyR" => |game| game.app.random_init(&mut game.rng),
+                "KeyC" => |game| game.app.clear(),
+                "KeyS" => |game| game.app.toggle_running(),
+                "KeyF" => |game| game.app.fit_to_win_size(),
+                "Space" => |game| game.app.step(),
+                "Equal" => |game| game.app.zoom(1.0),
+                "Minus" => |game| game.app.zoom(-1.0),
+                "KeyD" => |game| game.app.toggle_aggregate(),
+                "KeyZ" => |game| game.app.toggle_smooth_zoom(),
+                "KeyH" => |game| game.app.toggle_hud(),
+                "KeyM" => |game| game.app.toggle_minimap(),
+                "KeyE" => |game| game.app.cycle_edge(),
+                "KeyO" => |game| game.app.toggle_overlay(),
+                "KeyG" => |game| game.reset(),
+                "Digit1" => |game| game.app.select_tool(Tool::Freehand),
+                "Digit2" => |game| game.app.select_tool(Tool::Line),
+                "Digit3" => |game| game.app.select_tool(Tool::Rectangle { filled: false }),
+                "Digit4" => |game| game.app.select_tool(Tool::Rectangle { filled: true }),
+                "Digit5" => |game| game.app.select_tool(Tool::Ellipse { filled: false }),
+                "Digit6" => |game| game.app.select_tool(Tool::Ellipse { filled: true }),
+                _ => return false,
+            };
+            if pressed {
+                action(game);
+            }
+        }
+    }
+    true
+}
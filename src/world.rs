@@ -0,0 +1,480 @@
+use board::{Anchor, Board, BoolOp};
+use continuous::{self, ContinuousBoard, ContinuousRule};
+use geom::{Move, Point, Size};
+use grid::EdgeMode;
+use rand::Rng;
+use rule::{Neighbourhood, Rule};
+use rule_table::{RuleTable, TableNeighbourhood};
+use state_board::StateBoard;
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+/// A rule understood by the bit-packed `Board`, a rule table or a continuous-state rule.
+#[derive(Clone, Debug)]
+pub enum RuleSpec {
+    Life(Rule),
+    Table(RuleTable),
+    Continuous(ContinuousRule),
+}
+
+impl Default for RuleSpec {
+    fn default() -> Self {
+        RuleSpec::Life(Rule::default())
+    }
+}
+
+impl FromStr for RuleSpec {
+    type Err = String;
+
+    /// Parses a rule string, or loads a rule table by name or path (see `RuleTable::load`).
+    fn from_str(s: &str) -> Result<RuleSpec, String> {
+        let s = s.trim();
+        if s.starts_with("Lenia") || s.starts_with("SmoothLife") {
+            return s.parse().map(RuleSpec::Continuous);
+        }
+        match s.parse::<Rule>() {
+            Ok(rule) => Ok(RuleSpec::Life(rule)),
+            Err(e) => RuleTable::load(s).map(RuleSpec::Table).map_err(|_| e),
+        }
+    }
+}
+
+impl RuleSpec {
+    /// Returns the number of cell states, including the dead state.
+    pub fn states(&self) -> u32 {
+        match *self {
+            RuleSpec::Life(ref rule) => rule.states(),
+            RuleSpec::Table(ref rule) => rule.states(),
+            RuleSpec::Continuous(_) => 256,
+        }
+    }
+}
+
+impl fmt::Display for RuleSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuleSpec::Life(ref rule) => write!(f, "{}", rule),
+            RuleSpec::Table(ref rule) => write!(f, "{}", rule),
+            RuleSpec::Continuous(ref rule) => write!(f, "{}", rule),
+        }
+    }
+}
+
+/// The cells of the board together with the engine that steps them.
+#[derive(Clone, Debug)]
+pub enum World {
+    Life(Board),
+    Table(StateBoard),
+    Continuous(ContinuousBoard),
+}
+
+impl World {
+    pub fn new_empty(size: Size, rule: RuleSpec) -> World {
+        match rule {
+            RuleSpec::Life(rule) => World::Life(Board::with_rule(size, rule)),
+            RuleSpec::Table(rule) => World::Table(StateBoard::new_empty(size, rule)),
+            RuleSpec::Continuous(rule) => World::Continuous(ContinuousBoard::new_empty(size, rule)),
+        }
+    }
+
+    pub fn rule(&self) -> RuleSpec {
+        match *self {
+            World::Life(ref board) => RuleSpec::Life(board.rule().clone()),
+            World::Table(ref board) => RuleSpec::Table(board.rule().clone()),
+            World::Continuous(ref board) => RuleSpec::Continuous(board.rule().clone()),
+        }
+    }
+
+    /// Changes the rule, keeping the cells whose state exists under the new rule. A bounded
+    /// grid in the rule resizes the world, keeping the top-left cells.
+    pub fn set_rule(&mut self, rule: RuleSpec) {
+        if let World::Life(ref mut board) = *self {
+            if let RuleSpec::Life(ref rule) = rule {
+                if rule.grid().map_or(true, |grid| grid.size() == board.size()) {
+                    board.set_rule(rule.clone());
+                    return;
+                }
+            }
+        }
+        let mut world = World::new_empty(self.size(), rule);
+        world.copy_from(self, Point(0, 0));
+        world.inherit(self);
+        *self = world;
+    }
+
+    /// Takes the edge mode and expansion settings of `other`.
+    pub fn inherit(&mut self, other: &World) {
+        if let Some(edge) = other.edge() {
+            self.set_edge(edge);
+        }
+        self.set_max_size(other.max_size());
+    }
+
+    /// Returns the world coordinates of the top-left cell.
+    pub fn origin(&self) -> Point {
+        match *self {
+            World::Life(ref board) => board.origin(),
+            _ => Point(0, 0),
+        }
+    }
+
+    /// Sets the world coordinates of the top-left cell. Engines with a fixed origin ignore it.
+    pub fn set_origin(&mut self, origin: Point) {
+        if let World::Life(ref mut board) = *self {
+            board.set_origin(origin);
+        }
+    }
+
+    /// Returns the size up to which the board expands, or `None` if it has a fixed size.
+    pub fn max_size(&self) -> Option<Size> {
+        match *self {
+            World::Life(ref board) => board.max_size(),
+            _ => None,
+        }
+    }
+
+    /// Turns on expansion up to `max_size`, or off. Engines with a fixed size ignore it.
+    pub fn set_max_size(&mut self, max_size: Option<Size>) {
+        if let World::Life(ref mut board) = *self {
+            board.set_max_size(max_size);
+        }
+    }
+
+    /// Returns the edge mode, or `None` if the engine has a fixed one.
+    pub fn edge(&self) -> Option<EdgeMode> {
+        match *self {
+            World::Life(ref board) => Some(board.edge()),
+            _ => None,
+        }
+    }
+
+    /// Changes the edge mode. Engines with a fixed edge mode ignore it.
+    pub fn set_edge(&mut self, edge: EdgeMode) {
+        if let World::Life(ref mut board) = *self {
+            board.set_edge(edge);
+        }
+    }
+
+    /// Copies the cells of `other` with its top-left corner at `origin`.
+    pub fn copy_from(&mut self, other: &World, origin: Point) {
+        let size = other.size();
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let p = Point(origin.0 + x, origin.1 + y);
+                if self.contains(p) {
+                    self.set_state(p, other.state(Point(x, y)));
+                }
+            }
+        }
+    }
+
+    /// Changes the size of the world, keeping the cells at `anchor` in place. Does nothing if
+    /// a bounded grid fixes the size.
+    pub fn resize(&mut self, size: Size, anchor: Anchor) {
+        if !self.allows_size(size) {
+            return;
+        }
+        if let World::Life(ref mut board) = *self {
+            board.resize_anchored(size, anchor);
+            return;
+        }
+        let shift = anchor.shift(self.size(), size);
+        self.remap(size, |p| Point(p.0 - shift.0, p.1 - shift.1));
+    }
+
+    /// Moves the cells by `by`, dropping those moved out of the world.
+    pub fn translate(&mut self, by: Move) {
+        if let World::Life(ref mut board) = *self {
+            board.translate(by);
+            return;
+        }
+        let size = self.size();
+        self.remap(size, |p| Point(p.0 - by.0, p.1 - by.1));
+    }
+
+    /// Shrinks the world to the smallest rectangle holding all non-dead cells.
+    pub fn crop(&mut self) {
+        let (origin, size) = match self.bounding_box() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        if !self.allows_size(size) {
+            return;
+        }
+        if let World::Life(ref mut board) = *self {
+            board.crop();
+            return;
+        }
+        self.remap(size, |p| Point(p.0 + origin.0, p.1 + origin.1));
+    }
+
+    /// Returns the top-left corner and the size of the smallest rectangle holding all non-dead
+    /// cells, or `None` if there are none.
+    pub fn bounding_box(&self) -> Option<(Point, Size)> {
+        if let World::Life(ref board) = *self {
+            return board.bounding_box();
+        }
+        let Size(w, h) = self.size();
+        let mut bounds: Option<(Point, Point)> = None;
+        for y in 0..h {
+            for x in 0..w {
+                if self.state(Point(x, y)) != 0 {
+                    bounds = Some(match bounds {
+                        Some((min, max)) => {
+                            (Point(cmp::min(min.0, x), cmp::min(min.1, y)),
+                             Point(cmp::max(max.0, x), cmp::max(max.1, y)))
+                        }
+                        None => (Point(x, y), Point(x, y)),
+                    });
+                }
+            }
+        }
+        bounds.map(|(min, max)| (min, Size(max.0 - min.0 + 1, max.1 - min.1 + 1)))
+    }
+
+    /// Combines the cells of `other`, with its top-left cell at `offset`, into this world.
+    /// Non-dead cells count as alive, and the result holds only dead and live cells.
+    pub fn combine(&mut self, other: &World, offset: Point, op: BoolOp) {
+        if let (&mut World::Life(ref mut board), &World::Life(ref other)) = (&mut *self, other) {
+            board.combine(other, offset, op);
+            return;
+        }
+        let Size(w, h) = self.size();
+        for y in 0..h {
+            for x in 0..w {
+                let state = if self.combined(other, offset, op, Point(x, y)) { 1 } else { 0 };
+                self.set_state(Point(x, y), state);
+            }
+        }
+    }
+
+    /// Returns the number of live cells `combine` would leave.
+    pub fn count_combined(&self, other: &World, offset: Point, op: BoolOp) -> u32 {
+        if let (&World::Life(ref board), &World::Life(ref other)) = (self, other) {
+            return board.count_combined(other, offset, op);
+        }
+        let Size(w, h) = self.size();
+        let mut count = 0;
+        for y in 0..h {
+            for x in 0..w {
+                if self.combined(other, offset, op, Point(x, y)) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn combined(&self, other: &World, offset: Point, op: BoolOp, p: Point) -> bool {
+        let q = Point(p.0 - offset.0, p.1 - offset.1);
+        let mine = self.state(p) != 0;
+        let theirs = other.contains(q) && other.state(q) != 0;
+        op.apply(mine as u64, theirs as u64) != 0
+    }
+
+    pub fn flip_horizontal(&mut self) {
+        if let World::Life(ref mut board) = *self {
+            board.flip_horizontal();
+            return;
+        }
+        let size = self.size();
+        self.remap(size, |p| Point(size.0 - 1 - p.0, p.1));
+    }
+
+    pub fn flip_vertical(&mut self) {
+        if let World::Life(ref mut board) = *self {
+            board.flip_vertical();
+            return;
+        }
+        let size = self.size();
+        self.remap(size, |p| Point(p.0, size.1 - 1 - p.1));
+    }
+
+    /// Rotates the world a quarter turn clockwise. Does nothing if a bounded grid fixes the
+    /// size to a non-square one.
+    pub fn rotate_clockwise(&mut self) {
+        let size = self.size();
+        if !self.allows_size(Size(size.1, size.0)) {
+            return;
+        }
+        if let World::Life(ref mut board) = *self {
+            board.rotate_clockwise();
+            return;
+        }
+        self.remap(Size(size.1, size.0), |p| Point(p.1, size.1 - 1 - p.0));
+    }
+
+    /// Rotates the world a quarter turn counterclockwise. Does nothing if a bounded grid fixes
+    /// the size to a non-square one.
+    pub fn rotate_counterclockwise(&mut self) {
+        let size = self.size();
+        if !self.allows_size(Size(size.1, size.0)) {
+            return;
+        }
+        if let World::Life(ref mut board) = *self {
+            board.rotate_counterclockwise();
+            return;
+        }
+        self.remap(Size(size.1, size.0), |p| Point(size.0 - 1 - p.1, p.0));
+    }
+
+    pub fn rotate_180(&mut self) {
+        if let World::Life(ref mut board) = *self {
+            board.rotate_180();
+            return;
+        }
+        let size = self.size();
+        self.remap(size, |p| Point(size.0 - 1 - p.0, size.1 - 1 - p.1));
+    }
+
+    /// Returns false if a bounded grid fixes the size to something else.
+    fn allows_size(&self, size: Size) -> bool {
+        match *self {
+            World::Life(ref board) => board.rule().grid().map_or(true, |grid| grid.size() == size),
+            _ => true,
+        }
+    }
+
+    /// Replaces the world with one of `size`, taking each cell from `source(p)` of the old one.
+    /// The bit-packed `Board` has faster transformations of its own.
+    fn remap<F>(&mut self, size: Size, source: F)
+        where F: Fn(Point) -> Point
+    {
+        let mut world = World::new_empty(size, self.rule());
+        world.inherit(self);
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let p = source(Point(x, y));
+                if self.contains(p) {
+                    world.set_state(Point(x, y), self.state(p));
+                }
+            }
+        }
+        *self = world;
+    }
+
+    pub fn size(&self) -> Size {
+        match *self {
+            World::Life(ref board) => board.size(),
+            World::Table(ref board) => board.size(),
+            World::Continuous(ref board) => board.size(),
+        }
+    }
+
+    /// Returns the number of cell states, including the dead state.
+    pub fn states(&self) -> u32 {
+        self.rule().states()
+    }
+
+    /// Returns true if odd rows are shifted half a cell to the right.
+    pub fn is_hexagonal(&self) -> bool {
+        match *self {
+            World::Life(ref board) => board.rule().neighbourhood() == Neighbourhood::Hexagonal,
+            World::Table(ref board) => {
+                board.rule().neighbourhood() == TableNeighbourhood::Hexagonal
+            }
+            World::Continuous(_) => false,
+        }
+    }
+
+    /// Returns true if cells hold real values, with states standing for values from 0 to 1.
+    pub fn is_continuous(&self) -> bool {
+        match *self {
+            World::Continuous(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        match *self {
+            World::Life(ref board) => board.contains(p),
+            World::Table(ref board) => board.contains(p),
+            World::Continuous(ref board) => board.contains(p),
+        }
+    }
+
+    pub fn state(&self, p: Point) -> u8 {
+        match *self {
+            World::Life(ref board) => board.state(p),
+            World::Table(ref board) => board.state(p),
+            World::Continuous(ref board) => board.state(p),
+        }
+    }
+
+    pub fn set_state(&mut self, p: Point, state: u8) {
+        match *self {
+            World::Life(ref mut board) => board.set_state(p, state),
+            World::Table(ref mut board) => board.set_state(p, state),
+            World::Continuous(ref mut board) => board.set_state(p, state),
+        }
+    }
+
+    /// Returns the number of cells in a non-dead state.
+    pub fn population(&self) -> u32 {
+        match *self {
+            World::Life(ref board) => board.population(),
+            World::Table(ref board) => board.population(),
+            World::Continuous(ref board) => board.population(),
+        }
+    }
+
+    pub fn count_in_rect(&self, p: Point, size: Size) -> u32 {
+        match *self {
+            World::Life(ref board) => board.count_in_rect(p, size),
+            World::Table(ref board) => board.count_in_rect(p, size),
+            World::Continuous(ref board) => board.count_in_rect(p, size),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match *self {
+            World::Life(ref mut board) => board.clear(),
+            World::Table(ref mut board) => board.clear(),
+            World::Continuous(ref mut board) => board.clear(),
+        }
+    }
+
+    pub fn random_init<R>(&mut self, rng: &mut R)
+        where R: Rng
+    {
+        match *self {
+            World::Life(ref mut board) => board.random_init(rng),
+            World::Table(ref mut board) => board.random_init(rng),
+            World::Continuous(ref mut board) => board.random_init(rng),
+        }
+    }
+
+    pub fn grow(&mut self) {
+        match *self {
+            World::Life(ref mut board) => board.grow(),
+            World::Table(ref mut board) => board.grow(),
+            World::Continuous(ref mut board) => board.grow(),
+        }
+    }
+
+    /// Returns the color of a cell state.
+    pub fn color(&self, state: u8) -> [u8; 4] {
+        match *self {
+            World::Life(ref board) => life_color(state, board.rule().states()),
+            World::Table(ref board) => {
+                let c = board.rule().color(state);
+                [c[0], c[1], c[2], 255]
+            }
+            World::Continuous(_) => continuous::color_map(state),
+        }
+    }
+}
+
+/// Dying states of Generations rules fade from orange to dark red as they age.
+fn life_color(state: u8, states: u32) -> [u8; 4] {
+    match state {
+        0 => [0, 0, 0, 255],
+        1 => [255, 255, 255, 255],
+        _ => {
+            let t = ((state - 2) as f64) / (cmp::max(states, 4) - 3) as f64;
+            let mix = |a: f64, b: f64| (a + (b - a) * t).round() as u8;
+            [mix(255.0, 96.0), mix(160.0, 0.0), 0, 255]
+        }
+    }
+}